@@ -1,6 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
 
-use reqwest::header::{HeaderValue, ACCEPT, AUTHORIZATION};
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use reqwest::header::{
+    HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, CONTENT_LENGTH, LINK, RETRY_AFTER,
+};
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use tokio::time;
 
@@ -42,22 +48,126 @@ pub struct GistResponse {
     pub git_pull_url: String,
     pub git_push_url: String,
     pub description: Option<String>,
+    pub public: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub owner: Option<UserResponse>,
+    #[serde(default)]
+    pub comments: u64,
+    #[serde(default)]
+    pub truncated: bool,
+    #[serde(default)]
+    pub files: BTreeMap<String, GistFile>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<GistHistory>,
+    /// The other fields in the response, kept for the JSON output
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct GistFile {
+    pub filename: String,
+    #[serde(rename = "type")]
+    pub file_type: Option<String>,
+    pub language: Option<String>,
+    pub raw_url: String,
+    pub size: u64,
+    #[serde(default)]
+    pub truncated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// The other fields in the response, kept for the JSON output
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct GistHistory {
+    pub version: String,
+    pub user: Option<UserResponse>,
+    pub committed_at: DateTime<Utc>,
+    pub change_status: ChangeStatus,
+    /// The other fields in the response, kept for the JSON output
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChangeStatus {
+    pub total: u64,
+    pub additions: u64,
+    pub deletions: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CommentRequest {
+    pub body: String,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CommentResponse {
+    pub id: u64,
+    pub body: String,
+    pub user: Option<UserResponse>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// The other fields in the response, kept for the JSON output
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 pub type ListResponse = Vec<GistResponse>;
 
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ListOptions {
+    /// Fetch only the specified page instead of following all pages
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+    /// Stop fetching once this number of gists are collected
+    pub limit: Option<usize>,
+    /// Fetch only the gists updated after this time
+    pub since: Option<DateTime<Utc>>,
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct UpdateRequest {
     #[serde(skip_serializing_if = "HashMap::is_empty")]
-    pub files: HashMap<String, Option<FileMetadata>>,
+    /// Files to change, or `None` to remove them
+    pub files: HashMap<String, Option<FileUpdate>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
 }
 
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct FileUpdate {
+    /// New name of the file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct UserResponse {
     pub login: String,
     pub html_url: String,
+    /// The other fields in the response, kept for the JSON output
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AuthenticatedUser {
+    pub user: UserResponse,
+    /// Scopes granted to the OAuth token, or `None` if the token is not an OAuth token
+    pub scopes: Option<Vec<String>>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct RevokeTokenRequest {
+    access_token: String,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -71,6 +181,10 @@ pub struct VerificationCodeResponse {
     pub device_code: String,
     pub user_code: String,
     pub verification_uri: String,
+    /// `verification_uri` including the user code, which is not sent by GitHub for now
+    #[serde(default)]
+    pub verification_uri_complete: Option<String>,
+    pub expires_in: u64,
     pub interval: u64,
 }
 
@@ -84,33 +198,177 @@ struct AccessTokenRequest {
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 enum AccessTokenResponse {
-    AccessToken { access_token: String },
-    Error { error: String },
+    AccessToken {
+        access_token: String,
+        #[serde(default)]
+        scope: String,
+    },
+    Error {
+        error: String,
+        #[serde(default)]
+        interval: Option<u64>,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AccessToken {
+    pub login: Login,
+    /// Scopes granted to the token, which may differ from the requested ones
+    pub scopes: Vec<String>,
 }
 
+pub const DEFAULT_API_URL: &str = "https://api.github.com";
+pub const DEFAULT_WEB_URL: &str = "https://github.com";
+
+/// Maximum number of the retries of a request
+const MAX_RETRIES: u32 = 3;
+
+/// Longest time to wait for the rate limit, instead of failing with `ErrorKind::RateLimited`
+const MAX_RATE_LIMIT_WAIT: time::Duration = time::Duration::from_secs(60);
+
 pub struct Client {
     client: reqwest::Client,
+    api_url: String,
+    web_url: String,
+    /// When the rate limit is reset, set while no request remains
+    rate_limit_reset: Mutex<Option<DateTime<Utc>>>,
+    /// Called with the duration before waiting for the rate limit
+    on_rate_limit: Option<Box<dyn Fn(time::Duration) + Send + Sync>>,
 }
 
 impl Client {
-    pub fn build() -> Result<Self> {
+    pub fn build(api_url: &str, web_url: &str) -> Result<Self> {
         let b = reqwest::Client::builder().user_agent("reqwest");
-        Ok(Client { client: b.build()? })
+        Ok(Client {
+            client: b.build()?,
+            api_url: api_url.trim_end_matches('/').to_owned(),
+            web_url: web_url.trim_end_matches('/').to_owned(),
+            rate_limit_reset: Mutex::new(None),
+            on_rate_limit: None,
+        })
+    }
+
+    /// Sets the function to be notified of the wait for the rate limit, e.g. to tell the user why
+    /// the command stops.
+    pub fn on_rate_limit<F>(&mut self, f: F)
+    where
+        F: Fn(time::Duration) + Send + Sync + 'static,
+    {
+        self.on_rate_limit = Some(Box::new(f));
+    }
+
+    fn notify_rate_limit(&self, wait: time::Duration) {
+        if let Some(f) = &self.on_rate_limit {
+            f(wait);
+        }
+    }
+
+    /// Sends the request. Idempotent requests are retried with exponential backoff on server errors
+    /// and connection failures, and any request is retried after waiting for the rate limit.
+    async fn send(&self, builder: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let mut req = builder.build()?;
+        let idempotent = matches!(
+            *req.method(),
+            Method::GET | Method::HEAD | Method::PUT | Method::DELETE
+        );
+
+        let mut attempt = 0;
+        loop {
+            self.wait_for_rate_limit().await?;
+
+            let next = req.try_clone().filter(|_| attempt < MAX_RETRIES);
+            let retryable = next.is_some();
+            let res = self.client.execute(req).await;
+            let retry = match &res {
+                Ok(res) => {
+                    self.update_rate_limit(res.headers());
+                    retry_decision(res.status(), res.headers(), idempotent, attempt, retryable)?
+                }
+                Err(e) if idempotent && (e.is_timeout() || e.is_request()) => {
+                    Retry::Backoff(backoff(attempt))
+                }
+                Err(_) => Retry::No,
+            };
+            let delay = match retry {
+                Retry::No => None,
+                Retry::Backoff(delay) => Some(delay),
+                Retry::RateLimit(delay) => {
+                    self.notify_rate_limit(delay);
+                    Some(delay)
+                }
+            };
+
+            match (delay, next) {
+                (Some(delay), Some(next)) => {
+                    time::delay_for(delay).await;
+                    req = next;
+                    attempt += 1;
+                }
+                _ => return Ok(res?),
+            }
+        }
+    }
+
+    /// Records the reset of the rate limit if no request remains.
+    fn update_rate_limit(&self, headers: &HeaderMap) {
+        if let Some(remaining) = header_u64(headers, "x-ratelimit-remaining") {
+            *self.rate_limit_reset.lock().unwrap() = if remaining == 0 {
+                rate_limit_reset(headers)
+            } else {
+                None
+            };
+        }
+    }
+
+    /// Waits until the rate limit is reset if no request remains.
+    async fn wait_for_rate_limit(&self) -> Result<()> {
+        let reset = *self.rate_limit_reset.lock().unwrap();
+        // The reset may have passed already while waiting to retry the rejected request
+        if let Some(reset) = reset.filter(|r| *r > Utc::now()) {
+            let wait = until(&reset);
+            if wait > MAX_RATE_LIMIT_WAIT {
+                return Err(Error::new(ErrorKind::RateLimited { reset: Some(reset) }));
+            }
+            self.notify_rate_limit(wait);
+            time::delay_for(wait).await;
+            *self.rate_limit_reset.lock().unwrap() = None;
+        }
+        Ok(())
+    }
+
+    fn api(&self, path: &str) -> String {
+        format!("{}{}", self.api_url, path)
     }
 
-    pub async fn user(&self, login: &Login) -> Result<UserResponse> {
-        let res = self
+    fn web(&self, path: &str) -> String {
+        format!("{}{}", self.web_url, path)
+    }
+
+    pub async fn user(&self, login: &Login) -> Result<AuthenticatedUser> {
+        let req = self
             .client
-            .get("https://api.github.com/user")
+            .get(&self.api("/user"))
             .header(
                 ACCEPT,
                 HeaderValue::from_static("application/vnd.github.v3+json"),
             )
-            .auth(&login)
-            .send()
-            .await?;
+            .auth(login);
+        let res = self.send(req).await?;
         if res.status().is_success() {
-            Ok(res.json().await?)
+            let scopes = res
+                .headers()
+                .get("x-oauth-scopes")
+                .and_then(|v| v.to_str().ok())
+                .map(|v| {
+                    v.split(',')
+                        .map(|s| s.trim().to_owned())
+                        .filter(|s| !s.is_empty())
+                        .collect()
+                });
+            Ok(AuthenticatedUser {
+                user: res.json().await?,
+                scopes,
+            })
         } else {
             Err(Error::new(ErrorKind::ApiWithStatus {
                 status: res.status(),
@@ -120,13 +378,38 @@ impl Client {
     }
 
     pub async fn upload(&self, login: &Login, req: &UploadRequest) -> Result<GistResponse> {
-        let res = self
-            .client
-            .post("https://api.github.com/gists")
-            .auth(&login)
-            .json(&req)
-            .send()
-            .await?;
+        let req = self.client.post(&self.api("/gists")).auth(login).json(&req);
+        let res = self.send(req).await?;
+        if res.status().is_success() {
+            Ok(res.json::<GistResponse>().await?)
+        } else {
+            Err(Error::new(ErrorKind::ApiWithStatus {
+                status: res.status(),
+                message: res.text().await?,
+            }))
+        }
+    }
+
+    pub async fn get(&self, login: Option<&Login>, id: &str) -> Result<GistResponse> {
+        self.get_gist(login, format!("/gists/{}", id)).await
+    }
+
+    pub async fn get_revision(
+        &self,
+        login: Option<&Login>,
+        id: &str,
+        sha: &str,
+    ) -> Result<GistResponse> {
+        self.get_gist(login, format!("/gists/{}/{}", id, sha)).await
+    }
+
+    async fn get_gist(&self, login: Option<&Login>, path: String) -> Result<GistResponse> {
+        let mut builder = self.client.get(&self.api(&path));
+        if let Some(login) = login {
+            builder = builder.auth(login);
+        }
+
+        let res = self.send(builder).await?;
         if res.status().is_success() {
             Ok(res.json::<GistResponse>().await?)
         } else {
@@ -137,19 +420,44 @@ impl Client {
         }
     }
 
+    pub async fn raw(&self, login: Option<&Login>, url: &str) -> Result<String> {
+        Ok(self.raw_response(login, url).await?.text().await?)
+    }
+
+    /// Same as `raw`, but returns the content as it is, e.g. for the binary files.
+    pub async fn raw_bytes(&self, login: Option<&Login>, url: &str) -> Result<Vec<u8>> {
+        Ok(self.raw_response(login, url).await?.bytes().await?.to_vec())
+    }
+
+    async fn raw_response(&self, login: Option<&Login>, url: &str) -> Result<reqwest::Response> {
+        let mut builder = self.client.get(url);
+        if let Some(login) = login {
+            builder = builder.auth(login);
+        }
+
+        let res = self.send(builder).await?;
+        if res.status().is_success() {
+            Ok(res)
+        } else {
+            Err(Error::new(ErrorKind::ApiWithStatus {
+                status: res.status(),
+                message: res.text().await?,
+            }))
+        }
+    }
+
     pub async fn update(
         &self,
         login: &Login,
         id: &str,
         req: &UpdateRequest,
     ) -> Result<GistResponse> {
-        let res = self
+        let req = self
             .client
-            .patch(&format!("https://api.github.com/gists/{}", id))
-            .auth(&login)
-            .json(&req)
-            .send()
-            .await?;
+            .patch(&self.api(&format!("/gists/{}", id)))
+            .auth(login)
+            .json(&req);
+        let res = self.send(req).await?;
         if res.status().is_success() {
             Ok(res.json::<GistResponse>().await?)
         } else {
@@ -164,21 +472,85 @@ impl Client {
         &self,
         login: Option<&Login>,
         username: Option<&str>,
+        opts: &ListOptions,
     ) -> Result<ListResponse> {
-        let mut builder = if let Some(username) = username {
-            self.client
-                .get(&format!("https://api.github.com/users/{}/gists", username))
+        let url = if let Some(username) = username {
+            self.api(&format!("/users/{}/gists", username))
         } else {
-            self.client.get("https://api.github.com/gists")
+            self.api("/gists")
         };
+        self.list_pages(url, login, opts).await
+    }
 
-        if let Some(login) = login {
-            builder = builder.auth(&login);
+    pub async fn list_starred(&self, login: &Login, opts: &ListOptions) -> Result<ListResponse> {
+        self.list_pages(self.api("/gists/starred"), Some(login), opts)
+            .await
+    }
+
+    async fn list_pages<T: DeserializeOwned>(
+        &self,
+        url: String,
+        login: Option<&Login>,
+        opts: &ListOptions,
+    ) -> Result<Vec<T>> {
+        let mut query = Vec::new();
+        if let Some(page) = opts.page {
+            query.push(("page", page.to_string()));
+        }
+        match (opts.page, opts.per_page) {
+            (_, Some(per_page)) => query.push(("per_page", per_page.to_string())),
+            (None, None) => query.push(("per_page", "100".to_owned())),
+            (Some(_), None) => {}
+        }
+        if let Some(since) = opts.since {
+            query.push(("since", since.to_rfc3339_opts(SecondsFormat::Secs, true)));
+        }
+
+        let mut items = Vec::new();
+        let mut builder = self.client.get(&url).query(&query);
+        loop {
+            if let Some(login) = login {
+                builder = builder.auth(login);
+            }
+
+            let res = self.send(builder).await?;
+            if !res.status().is_success() {
+                return Err(Error::new(ErrorKind::ApiWithStatus {
+                    status: res.status(),
+                    message: res.text().await?,
+                }));
+            }
+
+            let next = match opts.page {
+                Some(_) => None,
+                None => next_link(res.headers()),
+            };
+            items.extend(res.json::<Vec<T>>().await?);
+
+            if let Some(limit) = opts.limit {
+                if items.len() >= limit {
+                    items.truncate(limit);
+                    break;
+                }
+            }
+
+            match next {
+                Some(next) => builder = self.client.get(&next),
+                None => break,
+            }
         }
 
-        let res = builder.send().await?;
+        Ok(items)
+    }
+
+    pub async fn delete(&self, login: &Login, id: &str) -> Result<()> {
+        let req = self
+            .client
+            .delete(&self.api(&format!("/gists/{}", id)))
+            .auth(login);
+        let res = self.send(req).await?;
         if res.status().is_success() {
-            Ok(res.json::<ListResponse>().await?)
+            Ok(())
         } else {
             Err(Error::new(ErrorKind::ApiWithStatus {
                 status: res.status(),
@@ -187,15 +559,29 @@ impl Client {
         }
     }
 
-    pub async fn list_starred(&self, login: &Login) -> Result<ListResponse> {
-        let res = self
+    pub async fn list_comments(
+        &self,
+        login: Option<&Login>,
+        id: &str,
+    ) -> Result<Vec<CommentResponse>> {
+        let url = self.api(&format!("/gists/{}/comments", id));
+        self.list_pages(url, login, &ListOptions::default()).await
+    }
+
+    pub async fn create_comment(
+        &self,
+        login: &Login,
+        id: &str,
+        req: &CommentRequest,
+    ) -> Result<CommentResponse> {
+        let req = self
             .client
-            .get("https://api.github.com/gists/starred")
-            .auth(&login)
-            .send()
-            .await?;
+            .post(&self.api(&format!("/gists/{}/comments", id)))
+            .auth(login)
+            .json(&req);
+        let res = self.send(req).await?;
         if res.status().is_success() {
-            Ok(res.json::<ListResponse>().await?)
+            Ok(res.json::<CommentResponse>().await?)
         } else {
             Err(Error::new(ErrorKind::ApiWithStatus {
                 status: res.status(),
@@ -204,13 +590,128 @@ impl Client {
         }
     }
 
-    pub async fn delete(&self, login: &Login, id: &str) -> Result<()> {
-        let res = self
+    pub async fn update_comment(
+        &self,
+        login: &Login,
+        id: &str,
+        comment_id: u64,
+        req: &CommentRequest,
+    ) -> Result<CommentResponse> {
+        let req = self
+            .client
+            .patch(&self.api(&format!("/gists/{}/comments/{}", id, comment_id)))
+            .auth(login)
+            .json(&req);
+        let res = self.send(req).await?;
+        if res.status().is_success() {
+            Ok(res.json::<CommentResponse>().await?)
+        } else {
+            Err(Error::new(ErrorKind::ApiWithStatus {
+                status: res.status(),
+                message: res.text().await?,
+            }))
+        }
+    }
+
+    pub async fn delete_comment(&self, login: &Login, id: &str, comment_id: u64) -> Result<()> {
+        let req = self
+            .client
+            .delete(&self.api(&format!("/gists/{}/comments/{}", id, comment_id)))
+            .auth(login);
+        let res = self.send(req).await?;
+        if res.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::ApiWithStatus {
+                status: res.status(),
+                message: res.text().await?,
+            }))
+        }
+    }
+
+    pub async fn star(&self, login: &Login, id: &str) -> Result<()> {
+        let req = self
+            .client
+            .put(&self.api(&format!("/gists/{}/star", id)))
+            .header(CONTENT_LENGTH, 0)
+            .auth(login);
+        let res = self.send(req).await?;
+        if res.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::ApiWithStatus {
+                status: res.status(),
+                message: res.text().await?,
+            }))
+        }
+    }
+
+    pub async fn unstar(&self, login: &Login, id: &str) -> Result<()> {
+        let req = self
             .client
-            .delete(&format!("https://api.github.com/gists/{}", id))
-            .auth(&login)
-            .send()
-            .await?;
+            .delete(&self.api(&format!("/gists/{}/star", id)))
+            .auth(login);
+        let res = self.send(req).await?;
+        if res.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::new(ErrorKind::ApiWithStatus {
+                status: res.status(),
+                message: res.text().await?,
+            }))
+        }
+    }
+
+    pub async fn is_starred(&self, login: &Login, id: &str) -> Result<bool> {
+        let req = self
+            .client
+            .get(&self.api(&format!("/gists/{}/star", id)))
+            .auth(login);
+        let res = self.send(req).await?;
+        if res.status().is_success() {
+            Ok(true)
+        } else if res.status() == StatusCode::NOT_FOUND {
+            Ok(false)
+        } else {
+            Err(Error::new(ErrorKind::ApiWithStatus {
+                status: res.status(),
+                message: res.text().await?,
+            }))
+        }
+    }
+
+    pub async fn fork(&self, login: &Login, id: &str) -> Result<GistResponse> {
+        let req = self
+            .client
+            .post(&self.api(&format!("/gists/{}/forks", id)))
+            .header(CONTENT_LENGTH, 0)
+            .auth(login);
+        let res = self.send(req).await?;
+        if res.status().is_success() {
+            Ok(res.json::<GistResponse>().await?)
+        } else {
+            Err(Error::new(ErrorKind::ApiWithStatus {
+                status: res.status(),
+                message: res.text().await?,
+            }))
+        }
+    }
+
+    pub async fn revoke_token(
+        &self,
+        client_id: &str,
+        client_secret: &str,
+        token: &str,
+    ) -> Result<()> {
+        let req = RevokeTokenRequest {
+            access_token: String::from(token),
+        };
+        let req = self
+            .client
+            .delete(&self.api(&format!("/applications/{}/token", client_id)))
+            .basic_auth(client_id, Some(client_secret))
+            .json(&req);
+        let res = self.send(req).await?;
         if res.status().is_success() {
             Ok(())
         } else {
@@ -230,13 +731,12 @@ impl Client {
             client_id: String::from(client_id),
             scope: String::from(scope),
         };
-        let res = self
+        let req = self
             .client
-            .post("https://github.com/login/device/code")
+            .post(&self.web("/login/device/code"))
             .header(ACCEPT, HeaderValue::from_static("application/json"))
-            .json(&req)
-            .send()
-            .await?;
+            .json(&req);
+        let res = self.send(req).await?;
         if res.status().is_success() {
             Ok(res.json().await?)
         } else {
@@ -252,29 +752,51 @@ impl Client {
         client_id: &str,
         device_code: &str,
         interval: u64,
-    ) -> Result<Login> {
+        expires_in: u64,
+    ) -> Result<AccessToken> {
         let req = AccessTokenRequest {
             client_id: String::from(client_id),
             device_code: String::from(device_code),
             grant_type: "urn:ietf:params:oauth:grant-type:device_code".to_owned(),
         };
+        let deadline = time::Instant::now() + time::Duration::from_secs(expires_in);
+        let mut interval = interval;
         loop {
             time::delay_for(time::Duration::from_secs(interval)).await;
+            if time::Instant::now() >= deadline {
+                return Err(Error::new(ErrorKind::DeviceCodeExpired));
+            }
 
-            let res = self
+            let req = self
                 .client
-                .post("https://github.com/login/oauth/access_token")
+                .post(&self.web("/login/oauth/access_token"))
                 .header(ACCEPT, HeaderValue::from_static("application/json"))
-                .json(&req)
-                .send()
-                .await?;
+                .json(&req);
+            let res = self.send(req).await?;
             if res.status().is_success() {
                 match res.json::<AccessTokenResponse>().await? {
-                    AccessTokenResponse::AccessToken { access_token } => {
-                        return Ok(Login::OAuth(access_token))
+                    AccessTokenResponse::AccessToken {
+                        access_token,
+                        scope,
+                    } => {
+                        return Ok(AccessToken {
+                            login: Login::OAuth(access_token),
+                            scopes: scope
+                                .split(',')
+                                .filter(|s| !s.is_empty())
+                                .map(String::from)
+                                .collect(),
+                        })
                     }
-                    AccessTokenResponse::Error { error } => match error.as_str() {
+                    AccessTokenResponse::Error {
+                        error,
+                        interval: new_interval,
+                    } => match error.as_str() {
                         "authorization_pending" => continue,
+                        // The interval must be increased by 5 seconds, RFC 8628 section 3.5
+                        "slow_down" => interval = new_interval.unwrap_or(interval + 5),
+                        "expired_token" => return Err(Error::new(ErrorKind::DeviceCodeExpired)),
+                        "access_denied" => return Err(Error::new(ErrorKind::AccessDenied)),
                         _ => return Err(Error::new(ErrorKind::Api { message: error })),
                     },
                 }
@@ -287,3 +809,285 @@ impl Client {
         }
     }
 }
+
+#[derive(Debug, PartialEq)]
+enum Retry {
+    No,
+    /// Retry after the backoff for the server error or the connection failure
+    Backoff(time::Duration),
+    /// Retry after waiting for the rate limit
+    RateLimit(time::Duration),
+}
+
+/// Decides whether and when to retry the request from the response. `retryable` is false if the
+/// request cannot be sent again, which makes the rate limit an error.
+fn retry_decision(
+    status: StatusCode,
+    headers: &HeaderMap,
+    idempotent: bool,
+    attempt: u32,
+    retryable: bool,
+) -> Result<Retry> {
+    let retry_after = header_u64(headers, RETRY_AFTER.as_str()).map(time::Duration::from_secs);
+
+    if status == StatusCode::FORBIDDEN || status == StatusCode::TOO_MANY_REQUESTS {
+        // The request rejected by the rate limit is not processed, so it is safe to retry even if
+        // it is not idempotent
+        let reset = rate_limit_reset(headers);
+        let wait = match (retry_after, header_u64(headers, "x-ratelimit-remaining")) {
+            (Some(wait), _) => wait,
+            (None, Some(0)) => reset.map_or(MAX_RATE_LIMIT_WAIT, |r| until(&r)),
+            // Forbidden for the other reasons
+            _ => return Ok(Retry::No),
+        };
+        if !retryable || wait > MAX_RATE_LIMIT_WAIT {
+            return Err(Error::new(ErrorKind::RateLimited { reset }));
+        }
+        return Ok(Retry::RateLimit(wait));
+    }
+
+    if status.is_server_error() && idempotent {
+        return Ok(Retry::Backoff(
+            retry_after.unwrap_or_else(|| backoff(attempt)),
+        ));
+    }
+
+    Ok(Retry::No)
+}
+
+fn rate_limit_reset(headers: &HeaderMap) -> Option<DateTime<Utc>> {
+    header_u64(headers, "x-ratelimit-reset").and_then(|t| Utc.timestamp_opt(t as i64, 0).single())
+}
+
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}
+
+/// 1, 2, 4, ... seconds
+fn backoff(attempt: u32) -> time::Duration {
+    time::Duration::from_secs(1 << attempt)
+}
+
+/// Time until the moment, plus a second for the clock skew.
+fn until(t: &DateTime<Utc>) -> time::Duration {
+    (*t - Utc::now()).to_std().unwrap_or_default() + time::Duration::from_secs(1)
+}
+
+fn next_link(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(LINK)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .find_map(|link| {
+            let mut params = link.split(';');
+            let url = params.next()?.trim();
+            let url = url.strip_prefix('<')?.strip_suffix('>')?;
+            if params.any(|p| p.trim() == r#"rel="next""#) {
+                Some(url.to_owned())
+            } else {
+                None
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link_headers(values: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for v in values {
+            headers.append(LINK, HeaderValue::from_static(v));
+        }
+        headers
+    }
+
+    #[test]
+    fn find_next_link() {
+        let headers = link_headers(&[
+            r#"<https://api.github.com/gists?page=2>; rel="next", <https://api.github.com/gists?page=5>; rel="last""#,
+        ]);
+        assert_eq!(
+            next_link(&headers).as_deref(),
+            Some("https://api.github.com/gists?page=2")
+        );
+
+        let headers = link_headers(&[
+            r#"<https://api.github.com/gists?page=1>; rel="prev""#,
+            r#"<https://api.github.com/gists?page=3>; rel="next""#,
+        ]);
+        assert_eq!(
+            next_link(&headers).as_deref(),
+            Some("https://api.github.com/gists?page=3")
+        );
+    }
+
+    #[test]
+    fn no_next_link() {
+        assert_eq!(next_link(&HeaderMap::new()), None);
+        let headers = link_headers(&[
+            r#"<https://api.github.com/gists?page=4>; rel="prev", <https://api.github.com/gists?page=1>; rel="first""#,
+        ]);
+        assert_eq!(next_link(&headers), None);
+    }
+
+    #[test]
+    fn keep_extra_fields() {
+        let json = serde_json::json!({
+            "url": "https://api.github.com/gists/aa5a315d61ae9438b18d",
+            "forks_url": "https://api.github.com/gists/aa5a315d61ae9438b18d/forks",
+            "id": "aa5a315d61ae9438b18d",
+            "node_id": "MDQ6R2lzdGFhNWEzMTVkNjFhZTk0MzhiMThk",
+            "html_url": "https://gist.github.com/aa5a315d61ae9438b18d",
+            "git_pull_url": "https://gist.github.com/aa5a315d61ae9438b18d.git",
+            "git_push_url": "https://gist.github.com/aa5a315d61ae9438b18d.git",
+            "description": null,
+            "public": true,
+            "created_at": "2010-04-14T02:15:15Z",
+            "updated_at": "2011-06-20T11:34:15Z",
+            "owner": {
+                "login": "octocat",
+                "id": 1,
+                "html_url": "https://github.com/octocat"
+            },
+            "comments": 0,
+            "truncated": false,
+            "files": {
+                "hello_world.rb": {
+                    "filename": "hello_world.rb",
+                    "type": "application/x-ruby",
+                    "language": "Ruby",
+                    "raw_url": "https://gist.githubusercontent.com/octocat/aa5a315d61ae9438b18d/raw/hello_world.rb",
+                    "size": 167,
+                    "truncated": false,
+                    "encoding": "utf-8"
+                }
+            }
+        });
+        let gist: GistResponse = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(gist.extra["node_id"], json["node_id"]);
+        assert_eq!(serde_json::to_value(&gist).unwrap(), json);
+    }
+
+    fn headers(values: &[(&'static str, String)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in values {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn is_rate_limited(res: Result<Retry>) -> bool {
+        match res {
+            Err(e) => matches!(e.kind(), ErrorKind::RateLimited { .. }),
+            Ok(_) => false,
+        }
+    }
+
+    #[test]
+    fn retry_on_rate_limit() {
+        let secs = time::Duration::from_secs;
+        let retry_after = headers(&[("retry-after", "5".to_owned())]);
+        for status in &[StatusCode::FORBIDDEN, StatusCode::TOO_MANY_REQUESTS] {
+            // Even if not idempotent
+            assert_eq!(
+                retry_decision(*status, &retry_after, false, 0, true).unwrap(),
+                Retry::RateLimit(secs(5))
+            );
+        }
+
+        let reset = (Utc::now() + chrono::Duration::seconds(10)).timestamp();
+        let exhausted = headers(&[
+            ("x-ratelimit-remaining", "0".to_owned()),
+            ("x-ratelimit-reset", reset.to_string()),
+        ]);
+        match retry_decision(StatusCode::FORBIDDEN, &exhausted, true, 0, true).unwrap() {
+            Retry::RateLimit(wait) => assert!(wait > secs(5) && wait <= secs(12), "{:?}", wait),
+            retry => panic!("{:?}", retry),
+        }
+
+        let no_reset = headers(&[("x-ratelimit-remaining", "0".to_owned())]);
+        assert_eq!(
+            retry_decision(StatusCode::FORBIDDEN, &no_reset, true, 0, true).unwrap(),
+            Retry::RateLimit(MAX_RATE_LIMIT_WAIT)
+        );
+    }
+
+    #[test]
+    fn fail_on_long_rate_limit() {
+        let reset = (Utc::now() + chrono::Duration::hours(1)).timestamp();
+        let exhausted = headers(&[
+            ("x-ratelimit-remaining", "0".to_owned()),
+            ("x-ratelimit-reset", reset.to_string()),
+        ]);
+        assert!(is_rate_limited(retry_decision(
+            StatusCode::FORBIDDEN,
+            &exhausted,
+            true,
+            0,
+            true
+        )));
+
+        let retry_after = headers(&[("retry-after", "120".to_owned())]);
+        assert!(is_rate_limited(retry_decision(
+            StatusCode::TOO_MANY_REQUESTS,
+            &retry_after,
+            true,
+            0,
+            true
+        )));
+
+        // The request which cannot be sent again
+        let retry_after = headers(&[("retry-after", "5".to_owned())]);
+        assert!(is_rate_limited(retry_decision(
+            StatusCode::FORBIDDEN,
+            &retry_after,
+            true,
+            0,
+            false
+        )));
+    }
+
+    #[test]
+    fn no_retry_on_forbidden() {
+        for h in &[
+            HeaderMap::new(),
+            headers(&[("x-ratelimit-remaining", "59".to_owned())]),
+        ] {
+            assert_eq!(
+                retry_decision(StatusCode::FORBIDDEN, h, true, 0, true).unwrap(),
+                Retry::No
+            );
+        }
+    }
+
+    #[test]
+    fn retry_on_server_error() {
+        let secs = time::Duration::from_secs;
+        let none = HeaderMap::new();
+        for (attempt, wait) in &[(0, 1), (1, 2), (2, 4)] {
+            assert_eq!(
+                retry_decision(StatusCode::BAD_GATEWAY, &none, true, *attempt, true).unwrap(),
+                Retry::Backoff(secs(*wait))
+            );
+        }
+        let retry_after = headers(&[("retry-after", "3".to_owned())]);
+        assert_eq!(
+            retry_decision(StatusCode::SERVICE_UNAVAILABLE, &retry_after, true, 0, true).unwrap(),
+            Retry::Backoff(secs(3))
+        );
+
+        // POST and PATCH may have been applied
+        assert_eq!(
+            retry_decision(StatusCode::BAD_GATEWAY, &none, false, 0, true).unwrap(),
+            Retry::No
+        );
+        for status in &[StatusCode::OK, StatusCode::NOT_FOUND] {
+            assert_eq!(
+                retry_decision(*status, &none, true, 0, true).unwrap(),
+                Retry::No
+            );
+        }
+    }
+}
@@ -1,43 +1,54 @@
+use std::cmp::Reverse;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::{self, Read};
-use std::path::Path;
+use std::fs::{DirBuilder, File, OpenOptions};
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use futures::stream::{self, StreamExt};
 
 use crate::api;
 use crate::config;
-use crate::error::Result;
+use crate::credential;
+use crate::desktop;
+use crate::error::{Error, ErrorKind, Result};
+use crate::output::{self, Format};
 
 pub async fn upload<P: AsRef<Path>>(
+    client: &api::Client,
     login: &config::Login,
     secret: bool,
     description: Option<&str>,
     files: &[P],
+    opts: &LoadOptions,
+    format: Format,
 ) -> Result<()> {
     let req = api::UploadRequest {
-        files: load_files(files)?,
+        files: load_files(files, opts)?,
         description: description.map(String::from),
         public: !secret,
     };
 
-    let client = api::Client::build()?;
-    let res = client.upload(&login, &req).await?;
-
-    println!("{}", res.html_url);
+    let res = client.upload(login, &req).await?;
 
-    Ok(())
+    print_gist(format, &res)
 }
 
 pub async fn upload_from_stdin(
+    client: &api::Client,
     login: &config::Login,
     secret: bool,
     filename: &str,
     description: Option<&str>,
+    binary: BinaryFile,
+    format: Format,
 ) -> Result<()> {
-    let mut buf = String::new();
-    std::io::stdin().read_to_string(&mut buf)?;
+    let mut buf = Vec::new();
+    std::io::stdin().read_to_end(&mut buf)?;
 
-    let mut files = HashMap::with_capacity(1);
-    files.insert(filename.into(), api::FileMetadata { content: buf });
+    let files = file_entries(filename, buf, binary, || "standard input".to_owned())?
+        .into_iter()
+        .collect();
 
     let req = api::UploadRequest {
         files,
@@ -45,71 +56,641 @@ pub async fn upload_from_stdin(
         public: !secret,
     };
 
-    let client = api::Client::build()?;
-    let res = client.upload(&login, &req).await?;
+    let res = client.upload(login, &req).await?;
 
-    println!("{}", res.html_url);
-
-    Ok(())
+    print_gist(format, &res)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn update<P: AsRef<Path>>(
+    client: &api::Client,
     login: &config::Login,
     id: &str,
     description: Option<&str>,
     files: &[P],
     files_to_remove: &[String],
+    files_to_rename: &[(String, String)],
+    opts: &LoadOptions,
+    confirm: &ConfirmOptions,
+    format: Format,
 ) -> Result<()> {
-    let files = load_files(files)?;
-    let files = files
-        .into_iter()
-        .map(|(k, v)| (k, Some(v)))
-        .chain(files_to_remove.iter().cloned().map(|s| (s, None)))
-        .collect();
+    let mut loaded = load_files(files, opts)?;
+    let mut files = HashMap::with_capacity(loaded.len() + files_to_remove.len());
+    for (from, to) in files_to_rename.iter() {
+        // The file uploaded with either name gives the new content of the renamed file
+        let content = loaded.remove(to).or_else(|| loaded.remove(from));
+        let update = api::FileUpdate {
+            filename: Some(to.clone()),
+            content: content.map(|f| f.content),
+        };
+        files.insert(from.clone(), Some(update));
+    }
+    for (name, f) in loaded.into_iter() {
+        let update = api::FileUpdate {
+            filename: None,
+            content: Some(f.content),
+        };
+        files.insert(name, Some(update));
+    }
+    for name in files_to_remove.iter() {
+        files.insert(name.clone(), None);
+    }
+
+    // Removing files cannot be undone, so ask for confirmation
+    let ask = !files_to_remove.is_empty() && !confirm.yes;
+    if ask || confirm.dry_run {
+        let gist = client.get(Some(login), id).await?;
+        let mut changes: Vec<_> = files
+            .iter()
+            .map(|(name, update)| {
+                let exists = gist.files.contains_key(name);
+                let change = match update {
+                    None => Change::Remove,
+                    Some(api::FileUpdate {
+                        filename: Some(to), ..
+                    }) => Change::Rename(to),
+                    Some(_) if exists => Change::Update,
+                    Some(_) => Change::Add,
+                };
+                (name.as_str(), change)
+            })
+            .collect();
+        changes.sort();
+        print_preview(format, &gist, description, &changes);
+
+        if confirm.dry_run {
+            return print_dry_run(format, &changes);
+        }
+        ask_confirmation(&format!(
+            "Remove {} files from the Gist?",
+            files_to_remove.len()
+        ))?;
+    }
 
     let req = api::UpdateRequest {
         files,
         description: description.map(String::from),
     };
 
-    let client = api::Client::build()?;
-    let res = client.update(&login, id, &req).await?;
+    let res = client.update(login, id, &req).await?;
 
-    println!("{}", res.html_url);
+    print_gist(format, &res)
+}
 
-    Ok(())
+/// Options of the commands removing data.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ConfirmOptions {
+    /// Do not ask for confirmation
+    pub yes: bool,
+    /// Only print what would change, without sending any mutating request
+    pub dry_run: bool,
 }
 
-fn load_files<P: AsRef<Path>>(files: &[P]) -> io::Result<HashMap<String, api::FileMetadata>> {
-    files
-        .iter()
-        .map(|p| {
-            let p = p.as_ref();
-            let mut buf = String::new();
-            let mut f = File::open(p)?;
-            f.read_to_string(&mut buf)?;
-
-            let filename = p.file_name().unwrap().to_str().unwrap().to_string();
-            Ok((filename, api::FileMetadata { content: buf }))
-        })
-        .collect()
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Change<'a> {
+    Add,
+    Update,
+    Remove,
+    Rename(&'a str),
 }
 
-pub async fn list(login: Option<&config::Login>, username: Option<&str>) -> Result<()> {
-    let client = api::Client::build()?;
-    let r = client.list(login, username).await?;
-    list_gists(&r);
-    Ok(())
+impl Change<'_> {
+    fn status(&self) -> String {
+        match self {
+            Change::Add => "add".to_owned(),
+            Change::Update => "update".to_owned(),
+            Change::Remove => "remove".to_owned(),
+            Change::Rename(to) => format!("rename to {}", to),
+        }
+    }
 }
 
-pub async fn list_starred(login: &config::Login) -> Result<()> {
-    let client = api::Client::build()?;
-    let r = client.list_starred(login).await?;
-    list_gists(&r);
-    Ok(())
+/// Prints the Gist and how its files change, to stdout in `text` or stderr in the other formats
+/// to keep their output parsable.
+fn print_preview(
+    format: Format,
+    gist: &api::GistResponse,
+    description: Option<&str>,
+    changes: &[(&str, Change)],
+) {
+    let mut lines = vec![format!(
+        "{} {}",
+        gist.id,
+        gist.description.as_deref().unwrap_or_default()
+    )];
+    if let Some(description) = description {
+        lines.push(format!("  description: {}", description));
+    }
+    let changed = |name: &str| changes.iter().find(|(n, _)| *n == name).map(|(_, c)| *c);
+    let names = gist.files.keys().map(String::as_str).chain(
+        changes
+            .iter()
+            .filter(|(_, c)| *c == Change::Add)
+            .map(|(n, _)| *n),
+    );
+    for name in names {
+        let line = match changed(name) {
+            Some(Change::Add) => format!("  + {}", name),
+            Some(Change::Update) => format!("  ~ {}", name),
+            Some(Change::Remove) => format!("  - {}", name),
+            Some(Change::Rename(to)) => format!("  > {} -> {}", name, to),
+            None => format!("    {}", name),
+        };
+        lines.push(line);
+    }
+
+    for line in lines.iter() {
+        if format == Format::Text {
+            println!("{}", line);
+        } else {
+            eprintln!("{}", line);
+        }
+    }
+}
+
+fn print_dry_run(format: Format, changes: &[(&str, Change)]) -> Result<()> {
+    if format == Format::Text {
+        println!("Dry run: nothing was changed");
+        Ok(())
+    } else {
+        let statuses: Vec<_> = changes
+            .iter()
+            .map(|(name, change)| output::FileStatus {
+                path: (*name).to_owned(),
+                status: change.status(),
+            })
+            .collect();
+        output::print(format, &statuses)
+    }
+}
+
+/// Asks on the terminal whether to proceed, and fails unless accepted.
+fn ask_confirmation(prompt: &str) -> Result<()> {
+    if !io::stdin().is_terminal() {
+        return Err(Error::new(ErrorKind::ConfirmationRequired));
+    }
+
+    eprint!("{} [y/N] ", prompt);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    match answer.trim() {
+        "y" | "Y" | "yes" => Ok(()),
+        _ => Err(Error::new(ErrorKind::Cancelled)),
+    }
+}
+
+fn print_gist(format: Format, gist: &api::GistResponse) -> Result<()> {
+    if format == Format::Text {
+        println!("{}", gist.html_url);
+        Ok(())
+    } else {
+        output::print_one(format, gist)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryFile {
+    Fail,
+    Skip,
+    Base64,
+}
+
+impl std::str::FromStr for BinaryFile {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(BinaryFile::Fail),
+            "skip" => Ok(BinaryFile::Skip),
+            "base64" => Ok(BinaryFile::Base64),
+            _ => Err(format!("invalid value '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadOptions {
+    pub binary: BinaryFile,
+    /// Walk into the directories, respecting `.gitignore` and `.gistignore`
+    pub recursive: bool,
+    /// Separator to join the path components of the files in the directories
+    pub separator: String,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        LoadOptions {
+            binary: BinaryFile::Fail,
+            recursive: false,
+            separator: "_".to_owned(),
+        }
+    }
+}
+
+fn load_files<P: AsRef<Path>>(
+    files: &[P],
+    opts: &LoadOptions,
+) -> Result<HashMap<String, api::FileMetadata>> {
+    let files = list_files(files, opts)?;
+
+    let mut seen = HashMap::with_capacity(files.len());
+    for (filename, p) in files.iter() {
+        claim_name(&mut seen, filename, p)?;
+    }
+
+    let mut loaded = HashMap::with_capacity(files.len());
+    for (filename, p) in files.iter() {
+        let mut buf = Vec::new();
+        let mut f = File::open(p)?;
+        f.read_to_end(&mut buf)?;
+
+        for (name, file) in file_entries(filename, buf, opts.binary, || p.display().to_string())? {
+            // The base64 text and the note of the binary file may conflict with the other files
+            if name != *filename {
+                claim_name(&mut seen, &name, p)?;
+            }
+            loaded.insert(name, file);
+        }
+    }
+    Ok(loaded)
+}
+
+fn claim_name<'a>(seen: &mut HashMap<String, &'a Path>, filename: &str, p: &'a Path) -> Result<()> {
+    match seen.insert(filename.to_owned(), p) {
+        Some(other) => Err(Error::new(ErrorKind::DuplicateFileName {
+            filename: filename.to_owned(),
+            paths: (other.to_path_buf(), p.to_path_buf()),
+        })),
+        None => Ok(()),
+    }
+}
+
+/// Lists the files with the names on gist. The files in the directories are named after their
+/// relative paths joined with the separator, since the name of gist file cannot contain `/`.
+/// The files given as `LOCAL:REMOTE` are named `REMOTE`, or prefixed with it for the directories.
+fn list_files<P: AsRef<Path>>(files: &[P], opts: &LoadOptions) -> Result<Vec<(String, PathBuf)>> {
+    let mut listed = Vec::with_capacity(files.len());
+    for p in files.iter() {
+        let (p, remote) = split_remote_name(p.as_ref());
+        if !p.is_dir() {
+            let filename = match remote {
+                Some(remote) => remote,
+                None => p.file_name().and_then(|n| n.to_str()).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidFileName {
+                        path: p.to_path_buf(),
+                    })
+                })?,
+            };
+            listed.push((filename.to_owned(), p.to_path_buf()));
+            continue;
+        }
+        if !opts.recursive {
+            return Err(Error::new(ErrorKind::IsDirectory {
+                path: p.to_path_buf(),
+            }));
+        }
+
+        // Only `.gitignore` and `.gistignore` exclude the files, not the standard filters for the
+        // hidden files, `.ignore`, and the global or repository-local excludes of git
+        let walker = ignore::WalkBuilder::new(p)
+            .hidden(false)
+            .ignore(false)
+            .git_global(false)
+            .git_exclude(false)
+            .require_git(false)
+            .add_custom_ignore_filename(".gistignore")
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .build();
+        for entry in walker {
+            let entry = entry?;
+            match entry.file_type() {
+                Some(t) if t.is_file() => {}
+                _ => continue,
+            }
+            if entry.file_name() == ".gitignore" || entry.file_name() == ".gistignore" {
+                continue;
+            }
+
+            let filename = entry
+                .path()
+                .strip_prefix(p)
+                .unwrap()
+                .iter()
+                .map(|c| c.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(&opts.separator);
+            let filename = match remote {
+                Some(remote) => format!("{}{}{}", remote, opts.separator, filename),
+                None => filename,
+            };
+            listed.push((filename, entry.into_path()));
+        }
+    }
+    Ok(listed)
+}
+
+/// Splits `LOCAL:REMOTE` into the local path and the name on gist, unless the path exists as it is.
+fn split_remote_name(p: &Path) -> (&Path, Option<&str>) {
+    if p.exists() {
+        return (p, None);
+    }
+    match p.to_str().and_then(|s| s.rsplit_once(':')) {
+        Some((local, remote))
+            if !local.is_empty() && !remote.is_empty() && !remote.contains(['/', '\\']) =>
+        {
+            (Path::new(local), Some(remote))
+        }
+        _ => (p, None),
+    }
 }
 
-fn list_gists(gists: &[api::GistResponse]) {
+/// Returns the files to upload for the contents, which are the text as it is, or the base64 text
+/// and the note how to restore it for the binary file.
+fn file_entries<F: FnOnce() -> String>(
+    filename: &str,
+    buf: Vec<u8>,
+    binary: BinaryFile,
+    name: F,
+) -> Result<Vec<(String, api::FileMetadata)>> {
+    let buf = match into_text(buf) {
+        Ok(content) => return Ok(vec![(filename.to_owned(), api::FileMetadata { content })]),
+        Err(buf) => buf,
+    };
+
+    match binary {
+        BinaryFile::Fail => Err(Error::new(ErrorKind::BinaryFile { name: name() })),
+        BinaryFile::Skip => {
+            eprintln!("Skipped '{}': binary or non-UTF-8 file", name());
+            Ok(Vec::new())
+        }
+        BinaryFile::Base64 => {
+            let encoded = format!("{}.base64", filename);
+            let note = format!(
+                "'{0}' is a binary file uploaded as base64 text in '{1}'.\n\
+                 To restore it, run: base64 -d {1} > {0}\n",
+                filename, encoded
+            );
+            Ok(vec![
+                (
+                    encoded,
+                    api::FileMetadata {
+                        content: base64::encode(&buf),
+                    },
+                ),
+                (
+                    format!("{}.README", filename),
+                    api::FileMetadata { content: note },
+                ),
+            ])
+        }
+    }
+}
+
+/// Returns the contents as `String` unless it looks like a binary, i.e. it contains NUL in the
+/// first 8000 bytes as Git does, or it is not valid UTF-8.
+fn into_text(buf: Vec<u8>) -> std::result::Result<String, Vec<u8>> {
+    if buf.iter().take(8000).any(|&b| b == 0) {
+        return Err(buf);
+    }
+    String::from_utf8(buf).map_err(|e| e.into_bytes())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExistingFile {
+    Fail,
+    Overwrite,
+    Skip,
+}
+
+pub async fn download<P: AsRef<Path>>(
+    client: &api::Client,
+    login: Option<&config::Login>,
+    id: &str,
+    dir: Option<P>,
+    existing: ExistingFile,
+    format: Format,
+) -> Result<()> {
+    let gist = client.get(login, id).await?;
+
+    let dir = dir.as_ref().map_or_else(|| Path::new(id), |d| d.as_ref());
+    if !dir.exists() {
+        DirBuilder::new().recursive(true).create(dir)?;
+    }
+
+    let mut written = Vec::with_capacity(gist.files.len());
+    for f in gist.files.values() {
+        let filename = match Path::new(&f.filename).file_name() {
+            Some(filename) => filename,
+            None => {
+                eprintln!("Skipped '{}': invalid file name", f.filename);
+                continue;
+            }
+        };
+        let path = dir.join(filename);
+        let content = file_bytes(client, login, f).await?;
+        let status = if write_file(&path, &content, existing)? {
+            if format == Format::Text {
+                println!("{}", path.display());
+            }
+            "written"
+        } else {
+            eprintln!("Skipped '{}': file already exists", path.display());
+            "skipped"
+        };
+        written.push(output::FileStatus {
+            path: path.display().to_string(),
+            status: status.to_owned(),
+        });
+    }
+
+    if format == Format::Text {
+        Ok(())
+    } else {
+        output::print(format, &written)
+    }
+}
+
+fn write_file<P: AsRef<Path>>(path: P, content: &[u8], existing: ExistingFile) -> Result<bool> {
+    let mut opts = OpenOptions::new();
+    opts.write(true);
+    if existing == ExistingFile::Overwrite {
+        opts.create(true).truncate(true);
+    } else {
+        opts.create_new(true);
+    }
+
+    let mut f = match opts.open(path.as_ref()) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            return match existing {
+                ExistingFile::Skip => Ok(false),
+                _ => Err(Error::new(ErrorKind::FileExists {
+                    path: path.as_ref().to_path_buf(),
+                })),
+            };
+        }
+        Err(e) => return Err(e.into()),
+    };
+    f.write_all(content)?;
+    Ok(true)
+}
+
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub public: Option<bool>,
+    /// Matches the gists having a file written in the language
+    pub language: Option<String>,
+    /// Matches the gists having a file whose name matches the pattern
+    pub filename: Option<globset::GlobMatcher>,
+    /// Matches the gists having no file whose name matches the pattern
+    pub no_filename: Option<globset::GlobMatcher>,
+    pub description: Option<regex::Regex>,
+    /// Matches the gists last updated before the time
+    pub updated_before: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        self.public.is_none()
+            && self.language.is_none()
+            && self.filename.is_none()
+            && self.no_filename.is_none()
+            && self.description.is_none()
+            && self.updated_before.is_none()
+    }
+
+    pub fn matches(&self, gist: &api::GistResponse) -> bool {
+        if let Some(public) = self.public {
+            if gist.public != public {
+                return false;
+            }
+        }
+
+        if let Some(language) = &self.language {
+            let found = gist.files.values().any(|f| match &f.language {
+                Some(l) => l.eq_ignore_ascii_case(language),
+                None => false,
+            });
+            if !found {
+                return false;
+            }
+        }
+
+        if let Some(filename) = &self.filename {
+            if !gist.files.keys().any(|f| filename.is_match(f)) {
+                return false;
+            }
+        }
+
+        if let Some(description) = &self.description {
+            match &gist.description {
+                Some(d) if description.is_match(d) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(no_filename) = &self.no_filename {
+            if gist.files.keys().any(|f| no_filename.is_match(f)) {
+                return false;
+            }
+        }
+
+        if let Some(updated_before) = &self.updated_before {
+            if gist.updated_at >= *updated_before {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sort {
+    Created,
+    Updated,
+    Name,
+}
+
+impl std::str::FromStr for Sort {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "created" => Ok(Sort::Created),
+            "updated" => Ok(Sort::Updated),
+            "name" => Ok(Sort::Name),
+            _ => Err(format!("invalid value '{}'", s)),
+        }
+    }
+}
+
+pub async fn list(
+    client: &api::Client,
+    login: Option<&config::Login>,
+    username: Option<&str>,
+    opts: &api::ListOptions,
+    filter: &Filter,
+    sort: Option<Sort>,
+    format: Format,
+) -> Result<()> {
+    let r = client
+        .list(login, username, &fetch_options(opts, filter, sort))
+        .await?;
+    list_gists(format, &select_gists(r, opts, filter, sort))
+}
+
+pub async fn list_starred(
+    client: &api::Client,
+    login: &config::Login,
+    opts: &api::ListOptions,
+    filter: &Filter,
+    sort: Option<Sort>,
+    format: Format,
+) -> Result<()> {
+    let r = client
+        .list_starred(login, &fetch_options(opts, filter, sort))
+        .await?;
+    list_gists(format, &select_gists(r, opts, filter, sort))
+}
+
+/// The limit must be applied after filtering and sorting, so fetch all the gists in that case.
+fn fetch_options(opts: &api::ListOptions, filter: &Filter, sort: Option<Sort>) -> api::ListOptions {
+    if filter.is_empty() && sort.is_none() {
+        opts.clone()
+    } else {
+        api::ListOptions {
+            limit: None,
+            ..opts.clone()
+        }
+    }
+}
+
+fn select_gists(
+    mut gists: api::ListResponse,
+    opts: &api::ListOptions,
+    filter: &Filter,
+    sort: Option<Sort>,
+) -> api::ListResponse {
+    gists.retain(|g| filter.matches(g));
+    match sort {
+        Some(Sort::Created) => gists.sort_by_key(|g| Reverse(g.created_at)),
+        Some(Sort::Updated) => gists.sort_by_key(|g| Reverse(g.updated_at)),
+        Some(Sort::Name) => gists.sort_by(|a, b| a.files.keys().next().cmp(&b.files.keys().next())),
+        None => {}
+    }
+    if let Some(limit) = opts.limit {
+        gists.truncate(limit);
+    }
+    gists
+}
+
+fn list_gists(format: Format, gists: &[api::GistResponse]) -> Result<()> {
+    if format != Format::Text {
+        return output::print(format, gists);
+    }
+
     for g in gists.iter() {
         if let Some(d) = &g.description {
             println!("{} {}", g.html_url, d);
@@ -117,32 +698,1080 @@ fn list_gists(gists: &[api::GistResponse]) {
             println!("{}", g.html_url);
         }
     }
+    Ok(())
 }
 
-pub async fn delete(login: &config::Login, id: &[String]) -> Result<()> {
-    let client = api::Client::build()?;
-    for i in id.iter() {
-        client.delete(login, &i).await?;
-        println!("{}", i);
+pub async fn view(
+    client: &api::Client,
+    login: Option<&config::Login>,
+    id: &str,
+    revision: Option<&str>,
+    filename: Option<&str>,
+    raw: bool,
+    format: Format,
+) -> Result<()> {
+    let mut gist = match revision {
+        Some(sha) => client.get_revision(login, id, sha).await?,
+        None => client.get(login, id).await?,
+    };
+
+    if let Some(filename) = filename {
+        if !gist.files.contains_key(filename) {
+            return Err(Error::new(ErrorKind::FileNotInGist {
+                id: id.to_owned(),
+                filename: filename.to_owned(),
+            }));
+        }
+        gist.files.retain(|k, _| k == filename);
+    }
+
+    if !raw {
+        match format {
+            Format::Text => {}
+            Format::Json => {
+                for f in gist.files.values_mut() {
+                    if f.truncated {
+                        f.content = Some(client.raw(login, &f.raw_url).await?);
+                        f.truncated = false;
+                    }
+                }
+                return output::print_one(format, &gist);
+            }
+            _ => {
+                let files: Vec<_> = gist.files.into_values().collect();
+                return output::print(format, &files);
+            }
+        }
+    }
+
+    for (i, f) in gist.files.values().enumerate() {
+        let content = file_content(client, login, f).await?;
+        if raw {
+            print!("{}", content);
+            continue;
+        }
+
+        if i > 0 {
+            println!();
+        }
+        if let Some(language) = &f.language {
+            println!("==> {} ({}, {} bytes) <==", f.filename, language, f.size);
+        } else {
+            println!("==> {} ({} bytes) <==", f.filename, f.size);
+        }
+        print!("{}", content);
+        if !content.ends_with('\n') {
+            println!();
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn history(
+    client: &api::Client,
+    login: Option<&config::Login>,
+    id: &str,
+    format: Format,
+) -> Result<()> {
+    let gist = client.get(login, id).await?;
+    if format != Format::Text {
+        return output::print(format, &gist.history);
+    }
+
+    for h in gist.history.iter() {
+        let user = h.user.as_ref().map_or("-", |u| u.login.as_str());
+        println!(
+            "{} {} {} +{} -{}",
+            h.version,
+            user,
+            output::timestamp(&h.committed_at),
+            h.change_status.additions,
+            h.change_status.deletions
+        );
+    }
+    Ok(())
+}
+
+async fn file_content(
+    client: &api::Client,
+    login: Option<&config::Login>,
+    file: &api::GistFile,
+) -> Result<String> {
+    match &file.content {
+        Some(content) if !file.truncated => Ok(content.clone()),
+        _ => client.raw(login, &file.raw_url).await,
+    }
+}
+
+/// Returns the content of the file as it is. The content in the response is used only if it is
+/// complete, since the invalid UTF-8 in the binary files is replaced with U+FFFD there.
+async fn file_bytes(
+    client: &api::Client,
+    login: Option<&config::Login>,
+    file: &api::GistFile,
+) -> Result<Vec<u8>> {
+    match &file.content {
+        Some(content)
+            if !file.truncated
+                && content.len() as u64 == file.size
+                && !content.contains(char::REPLACEMENT_CHARACTER) =>
+        {
+            Ok(content.clone().into_bytes())
+        }
+        _ => client.raw_bytes(login, &file.raw_url).await,
+    }
+}
+
+pub async fn list_comments(
+    client: &api::Client,
+    login: Option<&config::Login>,
+    id: &str,
+    format: Format,
+) -> Result<()> {
+    let comments = client.list_comments(login, id).await?;
+    if format != Format::Text {
+        return output::print(format, &comments);
+    }
+
+    for (i, c) in comments.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let user = c.user.as_ref().map_or("-", |u| u.login.as_str());
+        println!(
+            "==> {} {} {} <==",
+            c.id,
+            user,
+            output::timestamp(&c.created_at)
+        );
+        println!("{}", c.body.trim_end());
     }
-    println!("Success!");
     Ok(())
 }
 
-pub async fn login<P: AsRef<Path>>(path: P, client_id: &str) -> Result<()> {
-    let client = api::Client::build()?;
+pub async fn add_comment(
+    client: &api::Client,
+    login: &config::Login,
+    id: &str,
+    body: Option<&str>,
+    format: Format,
+) -> Result<()> {
+    let req = api::CommentRequest {
+        body: comment_body(body)?,
+    };
+
+    let res = client.create_comment(login, id, &req).await?;
+
+    print_comment(format, &res)
+}
+
+pub async fn edit_comment(
+    client: &api::Client,
+    login: &config::Login,
+    id: &str,
+    comment_id: u64,
+    body: Option<&str>,
+    format: Format,
+) -> Result<()> {
+    let req = api::CommentRequest {
+        body: comment_body(body)?,
+    };
+
+    let res = client.update_comment(login, id, comment_id, &req).await?;
+
+    print_comment(format, &res)
+}
+
+fn print_comment(format: Format, comment: &api::CommentResponse) -> Result<()> {
+    if format == Format::Text {
+        println!("{}", comment.id);
+        Ok(())
+    } else {
+        output::print_one(format, comment)
+    }
+}
+
+pub async fn delete_comment(
+    client: &api::Client,
+    login: &config::Login,
+    id: &str,
+    comment_id: u64,
+    format: Format,
+) -> Result<()> {
+    client.delete_comment(login, id, comment_id).await?;
+    print_status(format, &comment_id.to_string(), "deleted")
+}
+
+fn print_status(format: Format, id: &str, status: &str) -> Result<()> {
+    if format == Format::Text {
+        println!("Success!");
+        Ok(())
+    } else {
+        let status = output::Status {
+            id: id.to_owned(),
+            status: status.to_owned(),
+        };
+        output::print_one(format, &status)
+    }
+}
+
+fn comment_body(body: Option<&str>) -> Result<String> {
+    if let Some(body) = body {
+        return Ok(body.to_owned());
+    }
+
+    let mut buf = String::new();
+    std::io::stdin().read_to_string(&mut buf)?;
+    Ok(buf)
+}
+
+pub async fn star(
+    client: &api::Client,
+    login: &config::Login,
+    id: &str,
+    format: Format,
+) -> Result<()> {
+    client.star(login, id).await?;
+    print_status(format, id, "starred")
+}
+
+pub async fn unstar(
+    client: &api::Client,
+    login: &config::Login,
+    id: &str,
+    format: Format,
+) -> Result<()> {
+    client.unstar(login, id).await?;
+    print_status(format, id, "unstarred")
+}
+
+pub async fn is_starred(
+    client: &api::Client,
+    login: &config::Login,
+    id: &str,
+    format: Format,
+) -> Result<()> {
+    let status = if client.is_starred(login, id).await? {
+        "starred"
+    } else {
+        "not starred"
+    };
+    if format == Format::Text {
+        println!("{}", status);
+        Ok(())
+    } else {
+        let status = output::Status {
+            id: id.to_owned(),
+            status: status.to_owned(),
+        };
+        output::print_one(format, &status)
+    }
+}
+
+pub async fn fork(
+    client: &api::Client,
+    login: &config::Login,
+    id: &str,
+    format: Format,
+) -> Result<()> {
+    let res = client.fork(login, id).await?;
 
-    let vc = client.request_verification_code(client_id, "gist").await?;
+    print_gist(format, &res)
+}
 
-    println!("open {} and enter '{}'", vc.verification_uri, vc.user_code);
+/// Deletes the Gists, running up to `jobs` requests at once. Unlike the other commands, it keeps
+/// going past the failures and reports them at the end.
+pub async fn delete(
+    client: &api::Client,
+    login: &config::Login,
+    id: &[String],
+    jobs: usize,
+    confirm: &ConfirmOptions,
+    format: Format,
+) -> Result<()> {
+    if !confirm.yes || confirm.dry_run {
+        let mut gists = stream::iter(id.iter())
+            .map(|i| async move { (i, client.get(Some(login), i).await) })
+            .buffered(jobs.max(1));
+        while let Some((i, res)) = gists.next().await {
+            match res {
+                Ok(gist) => print_delete_preview(format, &gist),
+                Err(e) => eprintln!("{}: {}", i, e),
+            }
+        }
 
-    let login = client
-        .request_access_token(client_id, &vc.device_code, vc.interval)
+        if confirm.dry_run {
+            return print_delete_dry_run(format, id);
+        }
+        ask_confirmation(&format!("Delete {} Gists?", id.len()))?;
+    }
+
+    delete_gists(client, login, id, jobs, format).await
+}
+
+/// Deletes the authenticated user's Gists matching the filter. Unlike `delete`, the Gists to
+/// delete are always shown since they are not given explicitly.
+pub async fn delete_matching(
+    client: &api::Client,
+    login: &config::Login,
+    filter: &Filter,
+    jobs: usize,
+    confirm: &ConfirmOptions,
+    format: Format,
+) -> Result<()> {
+    let mut gists = client
+        .list(Some(login), None, &api::ListOptions::default())
         .await?;
+    gists.retain(|g| filter.matches(g));
+    if gists.is_empty() {
+        if format == Format::Text {
+            println!("No Gist matches");
+            return Ok(());
+        }
+        return output::print::<output::Status>(format, &[]);
+    }
+
+    for gist in gists.iter() {
+        print_delete_preview(format, gist);
+    }
+
+    let id: Vec<_> = gists.into_iter().map(|g| g.id).collect();
+    if confirm.dry_run {
+        return print_delete_dry_run(format, &id);
+    }
+    if !confirm.yes {
+        ask_confirmation(&format!("Delete {} Gists?", id.len()))?;
+    }
+
+    delete_gists(client, login, &id, jobs, format).await
+}
+
+fn print_delete_preview(format: Format, gist: &api::GistResponse) {
+    let changes: Vec<_> = gist
+        .files
+        .keys()
+        .map(|n| (n.as_str(), Change::Remove))
+        .collect();
+    print_preview(format, gist, None, &changes);
+}
 
-    config::save_config(path.as_ref(), &login)?;
+fn print_delete_dry_run(format: Format, id: &[String]) -> Result<()> {
+    if format == Format::Text {
+        println!("Dry run: nothing was deleted");
+        return Ok(());
+    }
+    let statuses: Vec<_> = id
+        .iter()
+        .map(|i| output::Status {
+            id: i.clone(),
+            status: "delete".to_owned(),
+        })
+        .collect();
+    output::print(format, &statuses)
+}
 
-    println!("Success!");
+/// Runs up to `jobs` deletions at once, going past the failures.
+async fn delete_gists(
+    client: &api::Client,
+    login: &config::Login,
+    id: &[String],
+    jobs: usize,
+    format: Format,
+) -> Result<()> {
+    let mut results = stream::iter(id.iter())
+        .map(|i| async move { (i, client.delete(login, i).await) })
+        .buffered(jobs.max(1));
+
+    let mut statuses = Vec::with_capacity(id.len());
+    let mut not_found = Vec::new();
+    let mut forbidden = Vec::new();
+    let mut failed = 0;
+    while let Some((i, res)) = results.next().await {
+        let status = match res {
+            Ok(()) => {
+                if format == Format::Text {
+                    println!("{}", i);
+                }
+                "deleted"
+            }
+            Err(e) => {
+                eprintln!("{}: {}", i, e);
+                failed += 1;
+                match e.kind() {
+                    ErrorKind::ApiWithStatus { status, .. }
+                        if *status == reqwest::StatusCode::NOT_FOUND =>
+                    {
+                        not_found.push(i.as_str());
+                        "not_found"
+                    }
+                    ErrorKind::ApiWithStatus { status, .. }
+                        if *status == reqwest::StatusCode::FORBIDDEN =>
+                    {
+                        forbidden.push(i.as_str());
+                        "forbidden"
+                    }
+                    _ => "failed",
+                }
+            }
+        };
+        statuses.push(output::Status {
+            id: i.clone(),
+            status: status.to_owned(),
+        });
+    }
 
+    if format != Format::Text {
+        output::print(format, &statuses)?;
+    } else if failed == 0 {
+        println!("Success!");
+    }
+
+    if failed == 0 {
+        return Ok(());
+    }
+    eprintln!("Deleted: {} of {}", id.len() - failed, id.len());
+    if !not_found.is_empty() {
+        eprintln!("Not found: {}", not_found.join(" "));
+    }
+    if !forbidden.is_empty() {
+        eprintln!("Forbidden: {}", forbidden.join(" "));
+    }
+    Err(Error::new(ErrorKind::PartialFailure {
+        failed,
+        total: id.len(),
+    }))
+}
+
+/// Client ID of the OAuth App used when neither `gist login <client id>` nor the profile gives it,
+/// specified with `GIST_CLIENT_ID` at build time.
+pub const DEFAULT_CLIENT_ID: Option<&str> = option_env!("GIST_CLIENT_ID");
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoginOptions {
+    /// Client ID of the OAuth App, or the one recorded in the profile if `None`
+    pub client_id: Option<String>,
+    /// Scopes requested in addition to the required ones
+    pub scopes: Vec<String>,
+    /// Storage of the credential, or the one recorded in the profile if `None`
+    pub storage: Option<credential::Storage>,
+    /// Time to wait for the authorization, or until the code expires if `None`
+    pub timeout: Option<Duration>,
+    /// Open the verification URL in the browser
+    pub browser: bool,
+}
+
+/// Logins with OAuth2 device flow.
+pub async fn login<P: AsRef<Path>>(
+    client: &api::Client,
+    path: P,
+    profile: &str,
+    opts: &LoginOptions,
+    format: Format,
+) -> Result<()> {
+    let mut cfg = if path.as_ref().exists() {
+        config::load_config(path.as_ref())?
+    } else {
+        config::Config::default()
+    };
+    let current = cfg.profiles.get(profile);
+    let storage = opts
+        .storage
+        .unwrap_or_else(|| current.map_or_else(Default::default, |p| p.storage));
+    let client_id = opts
+        .client_id
+        .clone()
+        .or_else(|| current.and_then(|p| p.client_id.clone()))
+        .or_else(|| DEFAULT_CLIENT_ID.map(String::from))
+        .ok_or_else(|| Error::new(ErrorKind::ClientIdNotSpecified))?;
+    let store = credential::open(storage, path.as_ref())?;
+
+    let mut scope: Vec<&str> = config::REQUIRED_SCOPES.to_vec();
+    for s in opts.scopes.iter() {
+        if !scope.contains(&s.as_str()) {
+            scope.push(s);
+        }
+    }
+    let vc = client
+        .request_verification_code(&client_id, &scope.join(" "))
+        .await?;
+
+    let uri = vc
+        .verification_uri_complete
+        .as_ref()
+        .unwrap_or(&vc.verification_uri);
+    let mut message = format!("open {} and enter '{}'", uri, vc.user_code);
+    if desktop::copy_to_clipboard(&vc.user_code) {
+        message.push_str(" (copied to the clipboard)");
+    }
+    if opts.browser && desktop::open_browser(uri) {
+        message.push_str("\nopened the URL in the browser");
+    }
+
+    // Keep stdout clean for the structured formats
+    if format == Format::Text {
+        println!("{}", message);
+    } else {
+        eprintln!("{}", message);
+    }
+
+    let expires_in = Duration::from_secs(vc.expires_in);
+    let deadline = Instant::now() + opts.timeout.map_or(expires_in, |t| t.min(expires_in));
+    let progress = io::stderr().is_terminal();
+
+    let poll = async {
+        let poll =
+            client.request_access_token(&client_id, &vc.device_code, vc.interval, vc.expires_in);
+        match opts.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, poll).await {
+                Ok(res) => res,
+                Err(_) => Err(Error::new(ErrorKind::LoginTimedOut)),
+            },
+            None => poll.await,
+        }
+    };
+    let spinner = async {
+        if progress {
+            spinner(deadline).await
+        } else {
+            std::future::pending().await
+        }
+    };
+    let token = tokio::select! {
+        res = poll => res,
+        never = spinner => match never {},
+        _ = tokio::signal::ctrl_c() => Err(Error::new(ErrorKind::Cancelled)),
+    };
+    if progress {
+        // Erase the spinner
+        eprint!("\r\x1b[2K");
+    }
+    let token = token?;
+    warn_missing_scopes(profile, &token.scopes);
+
+    let entry = cfg.profiles.entry(profile.to_owned()).or_default();
+    if let Some(store) = store {
+        store.save(profile, &token.login)?;
+        entry.login = None;
+    } else {
+        entry.login = Some(token.login);
+    }
+    entry.storage = storage;
+    entry.scopes = Some(token.scopes);
+    entry.client_id = Some(client_id);
+    config::save_config(path.as_ref(), &cfg)?;
+
+    print_status(format, profile, "logged in")
+}
+
+/// Shows the spinner and the remaining time on stderr until cancelled.
+async fn spinner(deadline: Instant) -> std::convert::Infallible {
+    const FRAMES: &[char] = &['|', '/', '-', '\\'];
+    loop {
+        for frame in FRAMES {
+            let remaining = deadline.saturating_duration_since(Instant::now()).as_secs();
+            eprint!(
+                "\r{} Waiting for the authorization... {}:{:02} remaining",
+                frame,
+                remaining / 60,
+                remaining % 60
+            );
+            let _ = io::stderr().flush();
+            tokio::time::delay_for(Duration::from_millis(250)).await;
+        }
+    }
+}
+
+/// Warns on stderr when the token lacks the scopes required by the commands.
+pub fn warn_missing_scopes(profile: &str, scopes: &[String]) {
+    let missing = config::missing_scopes(scopes);
+    if !missing.is_empty() {
+        eprintln!(
+            "warning: the credential of profile '{}' lacks scope '{}'. Run 'gist login' again",
+            profile,
+            missing.join("', '")
+        );
+    }
+}
+
+pub async fn auth_status(
+    client: &api::Client,
+    login: &config::Login,
+    format: Format,
+) -> Result<()> {
+    let res = client.user(login).await?;
+    let status = output::AuthStatus {
+        user: res.user.login,
+        token_type: match login {
+            config::Login::OAuth(_) => "oauth",
+            config::Login::PersonalAccessToken { .. } => "personal_access_token",
+        }
+        .to_owned(),
+        scopes: res.scopes,
+    };
+
+    if format != Format::Text {
+        return output::print_one(format, &status);
+    }
+
+    println!("Logged in as {}", status.user);
+    println!("Token type: {}", status.token_type);
+    if let Some(scopes) = &status.scopes {
+        println!("Scopes: {}", scopes.join(", "));
+    }
     Ok(())
 }
+
+/// Removes the credential of the profile. When `revoke` is given as a pair of client ID and client
+/// secret of the OAuth App, the OAuth token is also revoked.
+pub async fn logout<P: AsRef<Path>>(
+    client: &api::Client,
+    path: P,
+    profile: &str,
+    revoke: Option<(&str, &str)>,
+    format: Format,
+) -> Result<()> {
+    let mut cfg = if path.as_ref().exists() {
+        config::load_config(path.as_ref())?
+    } else {
+        config::Config::default()
+    };
+    let entry = cfg
+        .profiles
+        .get_mut(profile)
+        .ok_or_else(|| Error::new(ErrorKind::NotLoggedIn))?;
+
+    let store = credential::open(entry.storage, path.as_ref())?;
+    let login = match &store {
+        Some(store) => store.load(profile)?,
+        None => entry.login.take(),
+    };
+    let login = login.ok_or_else(|| Error::new(ErrorKind::NotLoggedIn))?;
+
+    if let Some((client_id, client_secret)) = revoke {
+        match &login {
+            config::Login::OAuth(token) => {
+                client.revoke_token(client_id, client_secret, token).await?
+            }
+            _ => return Err(Error::new(ErrorKind::RevokeNotSupported)),
+        }
+    }
+
+    if let Some(store) = store {
+        store.delete(profile)?;
+    }
+    config::save_config(path.as_ref(), &cfg)?;
+
+    print_status(format, profile, "logged out")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_local_remote() {
+        for (arg, local, remote) in &[
+            ("no-such-file.txt", "no-such-file.txt", None),
+            ("no-such-file.txt:a.txt", "no-such-file.txt", Some("a.txt")),
+            (
+                "dir/no-such-file:a:b.txt",
+                "dir/no-such-file:a",
+                Some("b.txt"),
+            ),
+            ("no-such-file.txt:", "no-such-file.txt:", None),
+            (":a.txt", ":a.txt", None),
+            (
+                "no-such-file.txt:dir/a.txt",
+                "no-such-file.txt:dir/a.txt",
+                None,
+            ),
+        ] {
+            assert_eq!(
+                split_remote_name(Path::new(arg)),
+                (Path::new(local), *remote),
+                "{}",
+                arg
+            );
+        }
+    }
+
+    // Windows doesn't allow `:` in file names
+    #[cfg(unix)]
+    #[test]
+    fn keep_existing_path_with_colon() {
+        let path = std::env::temp_dir().join(format!("gist-test-{}:a.txt", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let split = split_remote_name(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(split, (path.as_path(), None));
+    }
+
+    /// Directory removed on drop, unique to the test.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("gist-test-{}-{}", std::process::id(), name));
+            DirBuilder::new().recursive(true).create(&path).unwrap();
+            TempDir(path)
+        }
+
+        fn write(&self, path: &str, content: &str) {
+            let path = self.0.join(path);
+            DirBuilder::new()
+                .recursive(true)
+                .create(path.parent().unwrap())
+                .unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn listed_names<P: AsRef<Path>>(files: &[P], opts: &LoadOptions) -> Vec<String> {
+        let mut names: Vec<_> = list_files(files, opts)
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn list_files_in_directory() {
+        let dir = TempDir::new("list");
+        dir.write("a.txt", "a");
+        dir.write(".env", "hidden");
+        dir.write("src/bin/main.rs", "fn main() {}");
+        dir.write(".gitignore", "*.log\n");
+        dir.write("debug.log", "ignored by .gitignore");
+        dir.write(".gistignore", "secret.txt\n");
+        dir.write("src/secret.txt", "ignored by .gistignore");
+        dir.write(".ignore", "a.txt\n");
+        dir.write(".git/config", "[core]");
+
+        let root = dir.0.to_str().unwrap().to_owned();
+        let opts = LoadOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            listed_names(&[&root], &opts),
+            [".env", ".ignore", "a.txt", "src_bin_main.rs"]
+        );
+        assert_eq!(
+            listed_names(
+                &[format!("{}:project", root)],
+                &LoadOptions {
+                    separator: "--".to_owned(),
+                    ..opts.clone()
+                }
+            ),
+            [
+                "project--.env",
+                "project--.ignore",
+                "project--a.txt",
+                "project--src--bin--main.rs"
+            ]
+        );
+        assert_eq!(
+            listed_names(
+                &[
+                    format!("{}/a.txt", root),
+                    format!("{}/src/bin/main.rs:lib.rs", root)
+                ],
+                &LoadOptions::default()
+            ),
+            ["a.txt", "lib.rs"]
+        );
+
+        assert!(matches!(
+            list_files(&[root], &LoadOptions::default())
+                .unwrap_err()
+                .kind(),
+            ErrorKind::IsDirectory { .. }
+        ));
+    }
+
+    #[test]
+    fn reject_duplicate_file_names() {
+        let dir = TempDir::new("duplicate");
+        dir.write("a/main.rs", "");
+        dir.write("b/main.rs", "");
+        dir.write("a_main.rs", "");
+        let root = dir.0.to_str().unwrap();
+
+        let files = [format!("{}/a/main.rs", root), format!("{}/b/main.rs", root)];
+        assert!(matches!(
+            load_files(&files, &LoadOptions::default())
+                .unwrap_err()
+                .kind(),
+            ErrorKind::DuplicateFileName { .. }
+        ));
+
+        // Flattened `a/main.rs` conflicts with `a_main.rs`
+        let opts = LoadOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            load_files(&[root], &opts).unwrap_err().kind(),
+            ErrorKind::DuplicateFileName { .. }
+        ));
+    }
+
+    fn gist(
+        id: &str,
+        public: bool,
+        description: Option<&str>,
+        created_at: &str,
+        updated_at: &str,
+        files: &[(&str, Option<&str>)],
+    ) -> api::GistResponse {
+        let files: serde_json::Map<_, _> = files
+            .iter()
+            .map(|(name, language)| {
+                let file = serde_json::json!({
+                    "filename": name,
+                    "type": "text/plain",
+                    "language": language,
+                    "raw_url": format!("https://gist.githubusercontent.com/octocat/{}/raw/{}", id, name),
+                    "size": 0,
+                });
+                (name.to_string(), file)
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "html_url": format!("https://gist.github.com/{}", id),
+            "git_pull_url": format!("https://gist.github.com/{}.git", id),
+            "git_push_url": format!("https://gist.github.com/{}.git", id),
+            "description": description,
+            "public": public,
+            "created_at": created_at,
+            "updated_at": updated_at,
+            "owner": null,
+            "files": files,
+        }))
+        .unwrap()
+    }
+
+    fn gists() -> api::ListResponse {
+        vec![
+            gist(
+                "a1",
+                true,
+                Some("Dockerfile for metashell"),
+                "2020-01-01T00:00:00Z",
+                "2020-03-01T00:00:00Z",
+                &[("Dockerfile", Some("Dockerfile"))],
+            ),
+            gist(
+                "b2",
+                false,
+                Some("solution of the puzzle"),
+                "2020-02-01T00:00:00Z",
+                "2020-02-01T00:00:00Z",
+                &[("main.rs", Some("Rust")), ("input.txt", Some("Text"))],
+            ),
+            gist(
+                "c3",
+                true,
+                None,
+                "2020-03-01T00:00:00Z",
+                "2020-04-01T00:00:00Z",
+                &[("a.rs", Some("Rust")), ("notes", None)],
+            ),
+        ]
+    }
+
+    fn glob(pattern: &str) -> Option<globset::GlobMatcher> {
+        Some(globset::Glob::new(pattern).unwrap().compile_matcher())
+    }
+
+    fn ids(gists: &[api::GistResponse]) -> Vec<&str> {
+        gists.iter().map(|g| g.id.as_str()).collect()
+    }
+
+    #[test]
+    fn filter_gists() {
+        let cases: Vec<(Filter, &[&str])> = vec![
+            (Filter::default(), &["a1", "b2", "c3"]),
+            (
+                Filter {
+                    public: Some(true),
+                    ..Default::default()
+                },
+                &["a1", "c3"],
+            ),
+            (
+                Filter {
+                    public: Some(false),
+                    ..Default::default()
+                },
+                &["b2"],
+            ),
+            (
+                Filter {
+                    language: Some("rust".to_owned()),
+                    ..Default::default()
+                },
+                &["b2", "c3"],
+            ),
+            (
+                Filter {
+                    filename: glob("*.rs"),
+                    ..Default::default()
+                },
+                &["b2", "c3"],
+            ),
+            (
+                Filter {
+                    no_filename: glob("*.txt"),
+                    ..Default::default()
+                },
+                &["a1", "c3"],
+            ),
+            (
+                Filter {
+                    description: Some(regex::Regex::new("^(?i)dockerfile").unwrap()),
+                    ..Default::default()
+                },
+                &["a1"],
+            ),
+            (
+                Filter {
+                    updated_before: Some("2020-03-01T00:00:00Z".parse().unwrap()),
+                    ..Default::default()
+                },
+                &["b2"],
+            ),
+            (
+                Filter {
+                    public: Some(true),
+                    language: Some("Rust".to_owned()),
+                    ..Default::default()
+                },
+                &["c3"],
+            ),
+        ];
+        for (filter, expected) in cases {
+            let selected: Vec<_> = gists().into_iter().filter(|g| filter.matches(g)).collect();
+            assert_eq!(ids(&selected), expected, "{:?}", filter);
+        }
+    }
+
+    #[test]
+    fn sort_and_limit_gists() {
+        let opts = api::ListOptions::default();
+        let filter = Filter::default();
+        for (sort, expected) in &[
+            (None, ["a1", "b2", "c3"]),
+            (Some(Sort::Created), ["c3", "b2", "a1"]),
+            (Some(Sort::Updated), ["c3", "a1", "b2"]),
+            (Some(Sort::Name), ["a1", "c3", "b2"]),
+        ] {
+            let selected = select_gists(gists(), &opts, &filter, *sort);
+            assert_eq!(ids(&selected), expected, "{:?}", sort);
+        }
+
+        let opts = api::ListOptions {
+            limit: Some(1),
+            ..Default::default()
+        };
+        let filter = Filter {
+            language: Some("Rust".to_owned()),
+            ..Default::default()
+        };
+        let selected = select_gists(gists(), &opts, &filter, Some(Sort::Updated));
+        assert_eq!(ids(&selected), ["c3"]);
+    }
+
+    #[test]
+    fn fetch_all_pages_to_filter() {
+        let opts = api::ListOptions {
+            limit: Some(10),
+            ..Default::default()
+        };
+        let filter = Filter {
+            public: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(fetch_options(&opts, &Filter::default(), None), opts);
+        assert_eq!(fetch_options(&opts, &filter, None).limit, None);
+        assert_eq!(
+            fetch_options(&opts, &Filter::default(), Some(Sort::Name)).limit,
+            None
+        );
+    }
+
+    #[test]
+    fn detect_binary() {
+        assert_eq!(into_text(b"text\n".to_vec()), Ok("text\n".to_owned()));
+        assert_eq!(into_text(Vec::new()), Ok(String::new()));
+        assert!(into_text(b"\x89PNG\r\n\x1a\n".to_vec()).is_err());
+        assert!(into_text(b"a\0b".to_vec()).is_err());
+
+        // Only the first 8000 bytes are checked for NUL
+        let mut buf = vec![b'a'; 8000];
+        buf.push(0);
+        assert!(into_text(buf).is_ok());
+        let mut buf = vec![b'a'; 7999];
+        buf.push(0);
+        assert!(into_text(buf).is_err());
+    }
+
+    #[test]
+    fn upload_binary_as_base64() {
+        let buf = vec![0x89, 0x50, 0xff, 0x00];
+        let mut entries = file_entries("a.png", buf, BinaryFile::Base64, String::new).unwrap();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        let names: Vec<_> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["a.png.README", "a.png.base64"]);
+        assert_eq!(entries[1].1.content, "iVD/AA==");
+        assert!(entries[0]
+            .1
+            .content
+            .contains("base64 -d a.png.base64 > a.png"));
+
+        let buf = vec![0];
+        assert!(
+            file_entries("a", buf.clone(), BinaryFile::Skip, String::new)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(matches!(
+            file_entries("a", buf, BinaryFile::Fail, String::new)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::BinaryFile { .. }
+        ));
+    }
+
+    #[test]
+    fn reject_base64_name_conflicts() {
+        let dir = TempDir::new("base64");
+        dir.write("a.png", "\0");
+        dir.write("a.png.base64", "");
+        let root = dir.0.to_str().unwrap();
+
+        let opts = LoadOptions {
+            binary: BinaryFile::Base64,
+            ..Default::default()
+        };
+        let files = [format!("{}/a.png", root), format!("{}/a.png.base64", root)];
+        assert!(matches!(
+            load_files(&files, &opts).unwrap_err().kind(),
+            ErrorKind::DuplicateFileName { .. }
+        ));
+        let files = [format!("{}/a.png", root)];
+        assert_eq!(load_files(&files, &opts).unwrap().len(), 2);
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use structopt::StructOpt;
 
 use gist::error::{Error, ErrorKind, Result};
@@ -10,6 +10,27 @@ struct Args {
     #[structopt(long, parse(from_os_str))]
     config: Option<PathBuf>,
 
+    /// Specify profile in the configuration file
+    #[structopt(long, global = true, env = "GIST_PROFILE")]
+    profile: Option<String>,
+
+    /// Specify base URL of GitHub API
+    #[structopt(long, global = true, env = "GIST_API_URL")]
+    api_url: Option<String>,
+
+    /// Specify base URL of GitHub, used for OAuth2 device flow
+    #[structopt(long, global = true, env = "GIST_WEB_URL")]
+    web_url: Option<String>,
+
+    /// Specify output format
+    #[structopt(
+        long,
+        global = true,
+        default_value = "text",
+        possible_values = &["text", "json", "tsv", "table"]
+    )]
+    format: gist::output::Format,
+
     #[structopt(subcommand)]
     command: Subcommand,
 }
@@ -29,25 +50,103 @@ struct Account {
     password: Option<String>,
 }
 
+#[derive(Debug, StructOpt)]
+struct Confirm {
+    /// Do not ask for confirmation
+    #[structopt(short, long)]
+    yes: bool,
+
+    /// Print what would change without changing anything
+    #[structopt(long)]
+    dry_run: bool,
+}
+
+impl From<Confirm> for gist::app::ConfirmOptions {
+    fn from(c: Confirm) -> Self {
+        gist::app::ConfirmOptions {
+            yes: c.yes,
+            dry_run: c.dry_run,
+        }
+    }
+}
+
 #[derive(Debug, StructOpt)]
 enum Subcommand {
     /// Login to GitHub with OAuth2 device flow
     Login(Login),
+    /// Remove the stored credential
+    Logout(Logout),
+    /// Inspect the credential
+    Auth(Auth),
     /// Upload the files to GitHub Gist
     Upload(Upload),
     /// Update the gist
     Update(Update),
     /// Browse the gists
     List(List),
+    /// Print the files of the gist
+    #[structopt(alias = "get")]
+    View(View),
+    /// Download the files of the gist into a directory
+    #[structopt(alias = "clone")]
+    Download(Download),
+    /// List the revisions of the gist
+    History(History),
     /// Delete the gists
     Delete(Delete),
+    /// Star the gist
+    Star(Star),
+    /// Unstar the gist
+    Unstar(Unstar),
+    /// Fork the gist
+    Fork(Fork),
+    /// Manage the comments on the gist
+    Comment(Comment),
 }
 
 #[derive(Debug, StructOpt)]
 struct Login {
-    /// Client ID of your OAuth Apps
-    #[structopt(required = true)]
-    client_id: String,
+    /// Client ID of your OAuth Apps [default: the one used last time]
+    client_id: Option<String>,
+
+    /// Request the scope in addition to 'gist', e.g. 'read:user'
+    #[structopt(long = "scope", value_name = "SCOPE", number_of_values = 1)]
+    scopes: Vec<String>,
+
+    /// Give up waiting for the authorization after the seconds [default: until the code expires]
+    #[structopt(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
+
+    /// Do not open the verification URL in the browser
+    #[structopt(long)]
+    no_browser: bool,
+
+    /// Specify where to store the credential
+    #[structopt(long, possible_values = &["plaintext", "encrypted-file", "keyring"])]
+    storage: Option<gist::credential::Storage>,
+}
+
+#[derive(Debug, StructOpt)]
+struct Logout {
+    /// Revoke the OAuth2 access token of the OAuth App with the client ID
+    #[structopt(long, value_name = "CLIENT_ID", requires = "client-secret")]
+    revoke: Option<String>,
+
+    /// Client secret of the OAuth App, required to revoke the token
+    #[structopt(long, env = "GIST_CLIENT_SECRET", hide_env_values = true)]
+    client_secret: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Auth {
+    /// Show the user and the scopes of the credential
+    Status(AuthStatus),
+}
+
+#[derive(Debug, StructOpt)]
+struct AuthStatus {
+    #[structopt(flatten)]
+    account: Account,
 }
 
 #[derive(Debug, StructOpt)]
@@ -59,6 +158,10 @@ struct Upload {
     #[structopt(short)]
     secret: bool,
 
+    /// Upload the files as public gist even if the profile defaults to secret
+    #[structopt(long, conflicts_with = "secret")]
+    public: bool,
+
     /// Specify a default name of gist
     #[structopt(short, default_value = "file.txt")]
     filename: String,
@@ -67,7 +170,19 @@ struct Upload {
     #[structopt(short)]
     description: Option<String>,
 
-    /// Specify the files to upload
+    /// Specify how to handle binary or non-UTF-8 files
+    #[structopt(long, default_value = "fail", possible_values = &["fail", "skip", "base64"])]
+    binary: gist::app::BinaryFile,
+
+    /// Upload the files in the directories recursively
+    #[structopt(short = "R", long)]
+    recursive: bool,
+
+    /// Specify a separator to flatten the paths of the files in the directories
+    #[structopt(long, default_value = "_", parse(try_from_str = parse_separator))]
+    separator: String,
+
+    /// Specify the files to upload, as LOCAL:REMOTE to name them differently on gist
     #[structopt(name = "FILES", parse(from_os_str))]
     files: Vec<PathBuf>,
 }
@@ -78,20 +193,31 @@ struct Update {
     account: Account,
 
     /// Gist ID to update
-    #[structopt(required = true)]
+    #[structopt(required = true, parse(try_from_str = gist::reference::parse_id))]
     id: String,
 
     /// Add a description to gist
     #[structopt(short)]
     description: Option<String>,
 
-    /// Specify the files to add or update
+    /// Specify the files to add or update, as LOCAL:REMOTE to name them differently on gist
     #[structopt(short, value_name = "FILES", parse(from_os_str))]
     files: Vec<PathBuf>,
 
+    /// Rename the file on gist, as OLD=NEW
+    #[structopt(long = "rename", value_name = "OLD=NEW", number_of_values = 1, parse(try_from_str = parse_rename))]
+    files_to_rename: Vec<(String, String)>,
+
+    /// Specify how to handle binary or non-UTF-8 files
+    #[structopt(long, default_value = "fail", possible_values = &["fail", "skip", "base64"])]
+    binary: gist::app::BinaryFile,
+
     /// Specify the file names to remove
     #[structopt(short = "r", value_name = "FILES")]
     files_to_remove: Vec<String>,
+
+    #[structopt(flatten)]
+    confirm: Confirm,
 }
 
 #[derive(Debug, StructOpt)]
@@ -103,20 +229,257 @@ struct List {
     #[structopt(long, conflicts_with = "username")]
     starred: bool,
 
+    /// Maximum number of gists to list
+    #[structopt(short = "n", long)]
+    limit: Option<usize>,
+
+    /// Fetch only the specified page
+    #[structopt(long)]
+    page: Option<u32>,
+
+    /// Number of gists per page
+    #[structopt(long)]
+    per_page: Option<u32>,
+
+    /// List only the gists updated after the time, e.g. 2020-01-01T00:00:00Z
+    #[structopt(long, value_name = "TIMESTAMP")]
+    since: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// List only the public gists
+    #[structopt(long, conflicts_with = "secret-only")]
+    public_only: bool,
+
+    /// List only the secret gists
+    #[structopt(long)]
+    secret_only: bool,
+
+    /// List only the gists having a file written in the language
+    #[structopt(long)]
+    language: Option<String>,
+
+    /// List only the gists having a file whose name matches the glob pattern
+    #[structopt(long, value_name = "GLOB", parse(try_from_str = parse_glob))]
+    filename: Option<globset::GlobMatcher>,
+
+    /// List only the gists whose description matches the regular expression
+    #[structopt(long, value_name = "REGEX")]
+    description: Option<regex::Regex>,
+
+    /// Sort the gists
+    #[structopt(long, possible_values = &["created", "updated", "name"])]
+    sort: Option<gist::app::Sort>,
+
     /// List public gists for the specified user
     author: Option<String>,
 }
 
+#[derive(Debug, StructOpt)]
+struct View {
+    #[structopt(flatten)]
+    account: Account,
+
+    /// Gist ID to print
+    #[structopt(required = true, parse(try_from_str = gist::reference::parse_id))]
+    id: String,
+
+    /// Print the specified revision instead of the latest one
+    #[structopt(long, value_name = "SHA")]
+    revision: Option<String>,
+
+    /// Print only the specified file
+    #[structopt(long, value_name = "NAME")]
+    file: Option<String>,
+
+    /// Print the contents without headers
+    #[structopt(long)]
+    raw: bool,
+}
+
+#[derive(Debug, StructOpt)]
+struct Download {
+    #[structopt(flatten)]
+    account: Account,
+
+    /// Overwrite the existing files
+    #[structopt(long, conflicts_with = "skip")]
+    force: bool,
+
+    /// Keep the existing files
+    #[structopt(long)]
+    skip: bool,
+
+    /// Gist ID or URL to download
+    #[structopt(required = true, parse(try_from_str = gist::reference::parse_id))]
+    id: String,
+
+    /// Directory to write the files, defaults to the gist ID
+    #[structopt(parse(from_os_str))]
+    dir: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+struct History {
+    #[structopt(flatten)]
+    account: Account,
+
+    /// Gist ID to list the revisions
+    #[structopt(required = true, parse(try_from_str = gist::reference::parse_id))]
+    id: String,
+}
+
 #[derive(Debug, StructOpt)]
 struct Delete {
     #[structopt(flatten)]
     account: Account,
 
+    /// Number of the Gists deleted at once
+    #[structopt(short, long, default_value = "4")]
+    jobs: usize,
+
+    #[structopt(flatten)]
+    confirm: Confirm,
+
+    /// Delete the gists last updated before the age, e.g. 90d, 12h
+    #[structopt(long, value_name = "AGE", parse(try_from_str = parse_age))]
+    older_than: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// Delete the gists whose description matches the regular expression
+    #[structopt(long, value_name = "REGEX")]
+    description_matches: Option<regex::Regex>,
+
+    /// Delete only the secret gists
+    #[structopt(long)]
+    secret_only: bool,
+
+    /// Delete the gists having a file whose name matches the glob pattern
+    #[structopt(long, value_name = "GLOB", parse(try_from_str = parse_glob))]
+    filename: Option<globset::GlobMatcher>,
+
+    /// Delete the gists having no file whose name matches the glob pattern
+    #[structopt(long, value_name = "GLOB", parse(try_from_str = parse_glob))]
+    no_files_matching: Option<globset::GlobMatcher>,
+
     /// The ID of gist to delete
-    #[structopt(required = true)]
+    #[structopt(
+        required_unless_one = &[
+            "older-than",
+            "description-matches",
+            "secret-only",
+            "filename",
+            "no-files-matching",
+        ],
+        conflicts_with_all = &[
+            "older-than",
+            "description-matches",
+            "secret-only",
+            "filename",
+            "no-files-matching",
+        ],
+        parse(try_from_str = gist::reference::parse_id),
+    )]
     id: Vec<String>,
 }
 
+#[derive(Debug, StructOpt)]
+struct Star {
+    #[structopt(flatten)]
+    account: Account,
+
+    /// Check whether the gist is starred instead of starring it
+    #[structopt(long)]
+    check: bool,
+
+    /// The ID of gist to star
+    #[structopt(required = true, parse(try_from_str = gist::reference::parse_id))]
+    id: String,
+}
+
+#[derive(Debug, StructOpt)]
+struct Unstar {
+    #[structopt(flatten)]
+    account: Account,
+
+    /// The ID of gist to unstar
+    #[structopt(required = true, parse(try_from_str = gist::reference::parse_id))]
+    id: String,
+}
+
+#[derive(Debug, StructOpt)]
+struct Fork {
+    #[structopt(flatten)]
+    account: Account,
+
+    /// The ID of gist to fork
+    #[structopt(required = true, parse(try_from_str = gist::reference::parse_id))]
+    id: String,
+}
+
+#[derive(Debug, StructOpt)]
+enum Comment {
+    /// List the comments on the gist
+    List(CommentList),
+    /// Add a comment to the gist
+    Add(CommentAdd),
+    /// Edit the comment
+    Edit(CommentEdit),
+    /// Delete the comment
+    Delete(CommentDelete),
+}
+
+#[derive(Debug, StructOpt)]
+struct CommentList {
+    #[structopt(flatten)]
+    account: Account,
+
+    /// Gist ID to list the comments
+    #[structopt(required = true, parse(try_from_str = gist::reference::parse_id))]
+    id: String,
+}
+
+#[derive(Debug, StructOpt)]
+struct CommentAdd {
+    #[structopt(flatten)]
+    account: Account,
+
+    /// Gist ID to comment on
+    #[structopt(required = true, parse(try_from_str = gist::reference::parse_id))]
+    id: String,
+
+    /// Comment body, read from stdin if omitted
+    body: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+struct CommentEdit {
+    #[structopt(flatten)]
+    account: Account,
+
+    /// Gist ID of the comment
+    #[structopt(required = true, parse(try_from_str = gist::reference::parse_id))]
+    id: String,
+
+    /// The ID of comment to edit
+    #[structopt(required = true)]
+    comment_id: u64,
+
+    /// New comment body, read from stdin if omitted
+    body: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+struct CommentDelete {
+    #[structopt(flatten)]
+    account: Account,
+
+    /// Gist ID of the comment
+    #[structopt(required = true, parse(try_from_str = gist::reference::parse_id))]
+    id: String,
+
+    /// The ID of comment to delete
+    #[structopt(required = true)]
+    comment_id: u64,
+}
+
 fn main() {
     let args = Args::from_args();
 
@@ -128,64 +491,308 @@ fn main() {
 
     if let Err(e) = rt.block_on(run(args)) {
         eprintln!("{}", e);
-        std::process::exit(3);
+        match e.kind() {
+            // Same as the shells for the processes terminated with SIGINT
+            ErrorKind::Cancelled => std::process::exit(130),
+            _ => std::process::exit(3),
+        }
     }
 }
 
 async fn run(args: Args) -> Result<()> {
     let path = args.config.or_else(gist::config::default_config_file);
+    // Only the profiles and the stored credentials need the configuration file. The error is
+    // kept until then, so that a broken file doesn't prevent the other commands.
+    let (mut config, config_error) = match &path {
+        Some(p) if p.exists() => match gist::config::load_config(p) {
+            Ok(config) => (config, None),
+            Err(e) => (gist::config::Config::default(), Some(e)),
+        },
+        _ => (gist::config::Config::default(), None),
+    };
+
+    // The token in the environment variables is meant for the default account, and must not be
+    // sent to the API server of the profile chosen explicitly
+    let explicit = args.profile.is_some() || config.default_profile.is_some();
+    let profile_name = args
+        .profile
+        .clone()
+        .unwrap_or_else(|| config.default_profile().to_owned());
+    let profile = match config.profiles.remove(&profile_name) {
+        Some(profile) => profile,
+        // The profile is created by login, and the missing default profile means not logged in
+        None if args.profile.is_none() => gist::config::Profile::default(),
+        None => match args.command {
+            Subcommand::Login(_) => gist::config::Profile::default(),
+            // The profile may be missing because of the broken configuration file
+            _ => {
+                return Err(config_error.unwrap_or_else(|| {
+                    Error::new(ErrorKind::ProfileNotFound { name: profile_name })
+                }))
+            }
+        },
+    };
+    let mut client = gist::api::Client::build(
+        args.api_url
+            .as_deref()
+            .or(profile.api_url.as_deref())
+            .unwrap_or(gist::api::DEFAULT_API_URL),
+        args.web_url
+            .as_deref()
+            .or(profile.web_url.as_deref())
+            .unwrap_or(gist::api::DEFAULT_WEB_URL),
+    )?;
+    client.on_rate_limit(|wait| {
+        eprintln!("Rate limit exceeded. Waiting {} seconds", wait.as_secs());
+    });
+
+    let selected = SelectedProfile {
+        path,
+        name: profile_name,
+        profile,
+        explicit,
+        config_error,
+    };
+    let format = args.format;
 
     match args.command {
         Subcommand::Login(opt) => {
-            let path = path.ok_or_else(|| Error::new(ErrorKind::ConfigDirectoryNotDetected))?;
-            gist::app::login(path, &opt.client_id).await?;
+            let path =
+                (selected.path).ok_or_else(|| Error::new(ErrorKind::ConfigDirectoryNotDetected))?;
+            gist::app::login(
+                &client,
+                path,
+                &selected.name,
+                &gist::app::LoginOptions {
+                    client_id: opt.client_id,
+                    scopes: opt.scopes,
+                    storage: opt.storage,
+                    timeout: opt.timeout.map(std::time::Duration::from_secs),
+                    browser: !opt.no_browser,
+                },
+                format,
+            )
+            .await?;
+        }
+        Subcommand::Logout(opt) => {
+            let path =
+                (selected.path).ok_or_else(|| Error::new(ErrorKind::ConfigDirectoryNotDetected))?;
+            let revoke = opt.revoke.as_deref().zip(opt.client_secret.as_deref());
+            gist::app::logout(&client, path, &selected.name, revoke, format).await?;
+        }
+        Subcommand::Auth(Auth::Status(opt)) => {
+            let l = select_account(selected, opt.account)?;
+            gist::app::auth_status(&client, &l, format).await?;
         }
         Subcommand::Upload(opt) => {
-            let l = select_account(path, opt.account)?;
+            let secret = opt.secret || (selected.profile.secret && !opt.public);
+            let l = select_account(selected, opt.account)?;
             if opt.files.is_empty() {
                 gist::app::upload_from_stdin(
+                    &client,
                     &l,
-                    opt.secret,
+                    secret,
                     &opt.filename,
                     opt.description.as_deref(),
+                    opt.binary,
+                    format,
                 )
                 .await?;
             } else {
-                gist::app::upload(&l, opt.secret, opt.description.as_deref(), &opt.files).await?;
+                gist::app::upload(
+                    &client,
+                    &l,
+                    secret,
+                    opt.description.as_deref(),
+                    &opt.files,
+                    &gist::app::LoadOptions {
+                        binary: opt.binary,
+                        recursive: opt.recursive,
+                        separator: opt.separator,
+                    },
+                    format,
+                )
+                .await?;
             }
         }
         Subcommand::Update(opt) => {
-            let l = select_account(path, opt.account)?;
+            let l = select_account(selected, opt.account)?;
             gist::app::update(
+                &client,
                 &l,
                 &opt.id,
                 opt.description.as_deref(),
                 &opt.files,
                 &opt.files_to_remove,
+                &opt.files_to_rename,
+                &gist::app::LoadOptions {
+                    binary: opt.binary,
+                    ..Default::default()
+                },
+                &opt.confirm.into(),
+                format,
             )
             .await?;
         }
         Subcommand::List(opt) => {
-            let l = select_account(path, opt.account);
+            let l = select_account(selected, opt.account);
+            let list_opts = gist::api::ListOptions {
+                page: opt.page,
+                per_page: opt.per_page,
+                limit: opt.limit,
+                since: opt.since,
+            };
+            let filter = gist::app::Filter {
+                public: match (opt.public_only, opt.secret_only) {
+                    (true, _) => Some(true),
+                    (_, true) => Some(false),
+                    _ => None,
+                },
+                language: opt.language,
+                filename: opt.filename,
+                description: opt.description,
+                ..Default::default()
+            };
             if opt.starred {
-                gist::app::list_starred(&l?).await?;
+                gist::app::list_starred(&client, &l?, &list_opts, &filter, opt.sort, format)
+                    .await?;
             } else {
-                gist::app::list(l.ok().as_ref(), opt.author.as_deref()).await?;
+                gist::app::list(
+                    &client,
+                    optional_account(l)?.as_ref(),
+                    opt.author.as_deref(),
+                    &list_opts,
+                    &filter,
+                    opt.sort,
+                    format,
+                )
+                .await?;
             }
         }
+        Subcommand::View(opt) => {
+            let l = select_account(selected, opt.account);
+            gist::app::view(
+                &client,
+                optional_account(l)?.as_ref(),
+                &opt.id,
+                opt.revision.as_deref(),
+                opt.file.as_deref(),
+                opt.raw,
+                format,
+            )
+            .await?;
+        }
+        Subcommand::Download(opt) => {
+            let l = select_account(selected, opt.account);
+            let existing = if opt.force {
+                gist::app::ExistingFile::Overwrite
+            } else if opt.skip {
+                gist::app::ExistingFile::Skip
+            } else {
+                gist::app::ExistingFile::Fail
+            };
+            gist::app::download(
+                &client,
+                optional_account(l)?.as_ref(),
+                &opt.id,
+                opt.dir,
+                existing,
+                format,
+            )
+            .await?;
+        }
+        Subcommand::History(opt) => {
+            let l = select_account(selected, opt.account);
+            gist::app::history(&client, optional_account(l)?.as_ref(), &opt.id, format).await?;
+        }
         Subcommand::Delete(opt) => {
-            let l = select_account(path, opt.account)?;
-            gist::app::delete(&l, &opt.id).await?;
+            let l = select_account(selected, opt.account)?;
+            let confirm = opt.confirm.into();
+            if opt.id.is_empty() {
+                let filter = gist::app::Filter {
+                    public: if opt.secret_only { Some(false) } else { None },
+                    filename: opt.filename,
+                    no_filename: opt.no_files_matching,
+                    description: opt.description_matches,
+                    updated_before: opt.older_than,
+                    ..Default::default()
+                };
+                gist::app::delete_matching(&client, &l, &filter, opt.jobs, &confirm, format)
+                    .await?;
+            } else {
+                gist::app::delete(&client, &l, &opt.id, opt.jobs, &confirm, format).await?;
+            }
+        }
+        Subcommand::Star(opt) => {
+            let l = select_account(selected, opt.account)?;
+            if opt.check {
+                gist::app::is_starred(&client, &l, &opt.id, format).await?;
+            } else {
+                gist::app::star(&client, &l, &opt.id, format).await?;
+            }
+        }
+        Subcommand::Unstar(opt) => {
+            let l = select_account(selected, opt.account)?;
+            gist::app::unstar(&client, &l, &opt.id, format).await?;
+        }
+        Subcommand::Fork(opt) => {
+            let l = select_account(selected, opt.account)?;
+            gist::app::fork(&client, &l, &opt.id, format).await?;
+        }
+        Subcommand::Comment(Comment::List(opt)) => {
+            let l = select_account(selected, opt.account);
+            gist::app::list_comments(&client, optional_account(l)?.as_ref(), &opt.id, format)
+                .await?;
+        }
+        Subcommand::Comment(Comment::Add(opt)) => {
+            let l = select_account(selected, opt.account)?;
+            gist::app::add_comment(&client, &l, &opt.id, opt.body.as_deref(), format).await?;
+        }
+        Subcommand::Comment(Comment::Edit(opt)) => {
+            let l = select_account(selected, opt.account)?;
+            gist::app::edit_comment(
+                &client,
+                &l,
+                &opt.id,
+                opt.comment_id,
+                opt.body.as_deref(),
+                format,
+            )
+            .await?;
+        }
+        Subcommand::Comment(Comment::Delete(opt)) => {
+            let l = select_account(selected, opt.account)?;
+            gist::app::delete_comment(&client, &l, &opt.id, opt.comment_id, format).await?;
         }
     }
 
     Ok(())
 }
 
-fn select_account<P: AsRef<Path>>(
-    path: Option<P>,
-    account: Account,
-) -> Result<gist::config::Login> {
+/// The profile of the command, where the stored credential is looked up.
+struct SelectedProfile {
+    path: Option<PathBuf>,
+    name: String,
+    profile: gist::config::Profile,
+    /// Whether the profile is selected with `--profile` or `default_profile`
+    explicit: bool,
+    /// Error on loading the configuration file, reported when the stored credential is needed
+    config_error: Option<Box<Error>>,
+}
+
+/// Allows the commands to run without login, while the other errors such as the wrong
+/// passphrase are reported. The broken configuration file doesn't matter without login either.
+fn optional_account(login: Result<gist::config::Login>) -> Result<Option<gist::config::Login>> {
+    match login {
+        Ok(login) => Ok(Some(login)),
+        Err(e) => match e.kind() {
+            ErrorKind::NotLoggedIn | ErrorKind::InvalidConfigFormat { .. } => Ok(None),
+            _ => Err(e),
+        },
+    }
+}
+
+fn select_account(selected: SelectedProfile, account: Account) -> Result<gist::config::Login> {
     if let Some(token) = account.access_token {
         return Ok(gist::config::Login::OAuth(token));
     }
@@ -194,6 +801,145 @@ fn select_account<P: AsRef<Path>>(
         return Ok(gist::config::Login::PersonalAccessToken { username, token });
     }
 
-    let path = path.ok_or_else(|| Error::new(ErrorKind::ConfigDirectoryNotDetected))?;
-    Ok(gist::config::load_config(path)?)
+    if !selected.explicit {
+        for var in &["GIST_TOKEN", "GITHUB_TOKEN"] {
+            match std::env::var(var) {
+                Ok(token) if !token.is_empty() => return Ok(gist::config::Login::OAuth(token)),
+                _ => {}
+            }
+        }
+    }
+
+    if let Some(e) = selected.config_error {
+        return Err(e);
+    }
+    let profile = selected.profile;
+    if let Some(command) = &profile.credential_helper {
+        return gist::credential::run_helper(command, &selected.name);
+    }
+
+    let store = match &selected.path {
+        Some(path) => gist::credential::open(profile.storage, path)?,
+        None => None,
+    };
+    let login = match store {
+        Some(store) => store.load(&selected.name)?,
+        None => profile.login,
+    };
+    let login = login.ok_or_else(|| Error::new(ErrorKind::NotLoggedIn))?;
+    if let Some(scopes) = &profile.scopes {
+        gist::app::warn_missing_scopes(&selected.name, scopes);
+    }
+    Ok(login)
+}
+
+/// Parses the age like `90d`, in seconds (`s`), minutes (`m`), hours (`h`), days (`d`) or weeks
+/// (`w`), and returns the time the age ago.
+fn parse_age(s: &str) -> std::result::Result<chrono::DateTime<chrono::Utc>, String> {
+    time_before(chrono::Utc::now(), s)
+}
+
+fn time_before(
+    now: chrono::DateTime<chrono::Utc>,
+    age: &str,
+) -> std::result::Result<chrono::DateTime<chrono::Utc>, String> {
+    let invalid = || format!("invalid age '{}', e.g. 90d", age);
+    let unit = age.chars().last().ok_or_else(invalid)?;
+    let n: u64 = age[..age.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| invalid())?;
+    let unit_secs = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    n.checked_mul(unit_secs)
+        .and_then(|secs| chrono::Duration::from_std(std::time::Duration::from_secs(secs)).ok())
+        .and_then(|age| now.checked_sub_signed(age))
+        .ok_or_else(|| format!("age '{}' is too large", age))
+}
+
+fn parse_rename(s: &str) -> std::result::Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((from, to)) if !from.is_empty() && !to.is_empty() => {
+            Ok((from.to_owned(), to.to_owned()))
+        }
+        _ => Err(format!("invalid value '{}', e.g. old.txt=new.txt", s)),
+    }
+}
+
+/// The name of gist file cannot contain `/`, which is the reason to flatten the paths.
+fn parse_separator(s: &str) -> std::result::Result<String, String> {
+    if s.contains('/') {
+        Err(String::from("the separator cannot contain '/'"))
+    } else {
+        Ok(s.to_owned())
+    }
+}
+
+fn parse_glob(s: &str) -> std::result::Result<globset::GlobMatcher, globset::Error> {
+    Ok(globset::Glob::new(s)?.compile_matcher())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn parse_age_units() {
+        let now = chrono::Utc.with_ymd_and_hms(2020, 8, 30, 12, 0, 0).unwrap();
+        for (age, expected) in &[
+            ("30s", chrono::Utc.with_ymd_and_hms(2020, 8, 30, 11, 59, 30)),
+            ("90m", chrono::Utc.with_ymd_and_hms(2020, 8, 30, 10, 30, 0)),
+            ("12h", chrono::Utc.with_ymd_and_hms(2020, 8, 30, 0, 0, 0)),
+            ("90d", chrono::Utc.with_ymd_and_hms(2020, 6, 1, 12, 0, 0)),
+            ("2w", chrono::Utc.with_ymd_and_hms(2020, 8, 16, 12, 0, 0)),
+        ] {
+            assert_eq!(time_before(now, age), Ok(expected.unwrap()), "{}", age);
+        }
+    }
+
+    #[test]
+    fn reject_invalid_age() {
+        let now = chrono::Utc::now();
+        for age in &[
+            "",
+            "d",
+            "90",
+            "90y",
+            "-1d",
+            "1.5d",
+            "18446744073709551615w",
+            "9999999999w",
+        ] {
+            assert!(time_before(now, age).is_err(), "{}", age);
+        }
+    }
+
+    #[test]
+    fn parse_renames() {
+        assert_eq!(
+            parse_rename("old.txt=new.txt"),
+            Ok(("old.txt".to_owned(), "new.txt".to_owned()))
+        );
+        assert_eq!(
+            parse_rename("a=b=c"),
+            Ok(("a".to_owned(), "b=c".to_owned()))
+        );
+        for s in &["", "old.txt", "=new.txt", "old.txt="] {
+            assert!(parse_rename(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn parse_separators() {
+        assert_eq!(parse_separator("_"), Ok("_".to_owned()));
+        assert_eq!(parse_separator(""), Ok("".to_owned()));
+        assert!(parse_separator("/").is_err());
+        assert!(parse_separator("a/b").is_err());
+    }
 }
@@ -1,12 +1,63 @@
-use std::fs::{DirBuilder, File};
-use std::io::{BufReader, BufWriter};
+use std::collections::BTreeMap;
+use std::fs::{DirBuilder, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read};
 use std::path::{Path, PathBuf};
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
+use crate::credential::Storage;
 use crate::error::{Error, ErrorKind, Result};
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Scopes the commands need to access Gists
+pub const REQUIRED_SCOPES: &[&str] = &["gist"];
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    /// Profile used when `--profile` is not specified, defaults to `DEFAULT_PROFILE`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_profile: Option<String>,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Config {
+    pub fn default_profile(&self) -> &str {
+        self.default_profile.as_deref().unwrap_or(DEFAULT_PROFILE)
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    /// Credential of the profile, stored only when `storage` is `Plaintext`
+    #[serde(
+        flatten,
+        default,
+        deserialize_with = "deserialize_login",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub login: Option<Login>,
+    #[serde(default, skip_serializing_if = "Storage::is_plaintext")]
+    pub storage: Storage,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub web_url: Option<String>,
+    /// Scopes granted to the OAuth2 access token at `gist login`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<String>>,
+    /// Client ID of the OAuth App used by `gist login`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    /// Command printing the credential, used instead of the stored credential
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential_helper: Option<String>,
+    /// Upload the files as secret gist by default
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub secret: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", content = "value")]
 pub enum Login {
     #[serde(rename = "oauth")]
@@ -15,6 +66,40 @@ pub enum Login {
     PersonalAccessToken { username: String, token: String },
 }
 
+/// Deserializes the flattened `Login`. Serde turns a flattened `Option` failed to parse into `None`,
+/// which would lose the malformed credential at the next save, so report it as an error instead.
+fn deserialize_login<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Login>, D::Error> {
+    #[derive(Deserialize)]
+    struct Fields {
+        #[serde(rename = "type")]
+        login_type: Option<serde_json::Value>,
+        value: Option<serde_json::Value>,
+    }
+
+    let fields = Fields::deserialize(deserializer)?;
+    if fields.login_type.is_none() && fields.value.is_none() {
+        return Ok(None);
+    }
+    let login = serde_json::json!({
+        "type": fields.login_type,
+        "value": fields.value,
+    });
+    serde_json::from_value(login)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Returns the required scopes not in `scopes`.
+pub fn missing_scopes(scopes: &[String]) -> Vec<&'static str> {
+    REQUIRED_SCOPES
+        .iter()
+        .filter(|r| !scopes.iter().any(|s| s == *r))
+        .copied()
+        .collect()
+}
+
 pub fn default_config_dir() -> Option<PathBuf> {
     dirs::config_dir().map(|p| p.join("gist"))
 }
@@ -23,10 +108,11 @@ pub fn default_config_file() -> Option<PathBuf> {
     default_config_dir().map(|p| p.join("config.json"))
 }
 
-pub fn load_config<P: AsRef<Path>>(path: P) -> Result<Login> {
+/// Loads the configuration file. The file written by the older versions, which has only a single
+/// `Profile`, is loaded as the default profile.
+pub fn load_config<P: AsRef<Path>>(path: P) -> Result<Config> {
     let file = File::open(path.as_ref())?;
-    let reader = BufReader::new(file);
-    match serde_json::from_reader(reader) {
+    match read_config(BufReader::new(file)) {
         Ok(config) => Ok(config),
         Err(error) => Err(Error::new(ErrorKind::InvalidConfigFormat {
             path: path.as_ref().to_path_buf(),
@@ -35,12 +121,23 @@ pub fn load_config<P: AsRef<Path>>(path: P) -> Result<Login> {
     }
 }
 
-pub fn save_config<P: AsRef<Path>>(path: P, cfg: &Login) -> Result<()> {
-    let dir = path.as_ref().parent().unwrap();
-    if !dir.exists() {
-        DirBuilder::new().recursive(true).create(dir)?;
+fn read_config<R: Read>(reader: R) -> serde_json::Result<Config> {
+    let v: serde_json::Value = serde_json::from_reader(reader)?;
+    if v.get("profiles").is_some() {
+        serde_json::from_value(v)
+    } else {
+        let profile = serde_json::from_value(v)?;
+        let mut profiles = BTreeMap::new();
+        profiles.insert(DEFAULT_PROFILE.to_owned(), profile);
+        Ok(Config {
+            default_profile: None,
+            profiles,
+        })
     }
-    let file = File::create(path.as_ref())?;
+}
+
+pub fn save_config<P: AsRef<Path>>(path: P, cfg: &Config) -> Result<()> {
+    let file = create_private_file(path.as_ref())?;
     let writer = BufWriter::new(file);
     match serde_json::to_writer_pretty(writer, cfg) {
         Ok(()) => Ok(()),
@@ -50,3 +147,95 @@ pub fn save_config<P: AsRef<Path>>(path: P, cfg: &Login) -> Result<()> {
         })),
     }
 }
+
+/// Creates the file readable and writable only by the owner, since it may contain credentials.
+pub fn create_private_file<P: AsRef<Path>>(path: P) -> io::Result<File> {
+    let dir = path.as_ref().parent().unwrap();
+    if !dir.exists() {
+        DirBuilder::new().recursive(true).create(dir)?;
+    }
+
+    let mut opts = OpenOptions::new();
+    opts.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        opts.mode(0o600);
+        let file = opts.open(path.as_ref())?;
+        // The mode is applied only when the file is newly created
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        Ok(file)
+    }
+    #[cfg(not(unix))]
+    opts.open(path.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_profile_login() {
+        let cfg = read_config(
+            r#"{"profiles": {"default": {"type": "oauth", "value": "token"}, "empty": {}}}"#
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.profiles["default"].login,
+            Some(Login::OAuth("token".to_owned()))
+        );
+        assert_eq!(cfg.profiles["empty"].login, None);
+    }
+
+    #[test]
+    fn reject_malformed_login() {
+        for s in &[
+            r#"{"profiles": {"default": {"type": "oath", "value": "token"}}}"#,
+            r#"{"profiles": {"default": {"type": "oauth"}}}"#,
+            r#"{"profiles": {"default": {"value": "token"}}}"#,
+            r#"{"type": "personal_access_token", "value": "token"}"#,
+        ] {
+            assert!(read_config(s.as_bytes()).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn migrate_legacy_config() {
+        let cfg = read_config(r#"{"type": "oauth", "value": "token"}"#.as_bytes()).unwrap();
+        assert_eq!(cfg.default_profile, None);
+        assert_eq!(cfg.profiles.len(), 1);
+        assert_eq!(
+            cfg.profiles[DEFAULT_PROFILE].login,
+            Some(Login::OAuth("token".to_owned()))
+        );
+
+        let cfg = read_config(
+            r#"{"type": "personal_access_token", "value": {"username": "octocat", "token": "token"}}"#
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(
+            cfg.profiles[DEFAULT_PROFILE].login,
+            Some(Login::PersonalAccessToken {
+                username: "octocat".to_owned(),
+                token: "token".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn read_profiles() {
+        let cfg = read_config(
+            r#"{"default_profile": "work", "profiles": {"work": {"api_url": "https://github.example.com/api/v3"}}}"#
+                .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(cfg.default_profile(), "work");
+        assert_eq!(
+            cfg.profiles["work"].api_url.as_deref(),
+            Some("https://github.example.com/api/v3")
+        );
+        assert_eq!(cfg.profiles["work"].login, None);
+    }
+}
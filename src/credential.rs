@@ -0,0 +1,353 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, Login};
+use crate::error::{Error, ErrorKind, Result};
+
+/// Where the credential of the profile is stored.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Storage {
+    /// In the configuration file as it is
+    #[default]
+    Plaintext,
+    /// In the file encrypted with a passphrase, next to the configuration file
+    EncryptedFile,
+    /// In the keyring provided by the OS, e.g. Secret Service on Linux
+    Keyring,
+}
+
+impl Storage {
+    pub fn is_plaintext(&self) -> bool {
+        *self == Storage::Plaintext
+    }
+}
+
+impl std::str::FromStr for Storage {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "plaintext" => Ok(Storage::Plaintext),
+            "encrypted-file" => Ok(Storage::EncryptedFile),
+            "keyring" => Ok(Storage::Keyring),
+            _ => Err(format!("invalid value '{}'", s)),
+        }
+    }
+}
+
+pub trait CredentialStore {
+    fn load(&self, profile: &str) -> Result<Option<Login>>;
+    fn save(&self, profile: &str, login: &Login) -> Result<()>;
+    fn delete(&self, profile: &str) -> Result<()>;
+}
+
+/// Opens the store of the credentials. `Plaintext` has no store since the credentials are kept in
+/// the configuration file itself.
+pub fn open<P: AsRef<Path>>(
+    storage: Storage,
+    config_path: P,
+) -> Result<Option<Box<dyn CredentialStore>>> {
+    match storage {
+        Storage::Plaintext => Ok(None),
+        Storage::EncryptedFile => {
+            let path = config_path.as_ref().with_file_name("credentials.enc");
+            Ok(Some(Box::new(EncryptedFile::new(path))))
+        }
+        Storage::Keyring => keyring_store(),
+    }
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_store() -> Result<Option<Box<dyn CredentialStore>>> {
+    Ok(Some(Box::new(Keyring)))
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_store() -> Result<Option<Box<dyn CredentialStore>>> {
+    Err(Error::new(ErrorKind::KeyringNotSupported))
+}
+
+/// Runs the credential helper command with `sh -c` and reads the credential from its stdout.
+///
+/// The output is either the token itself, or `key=value` lines like `git credential fill` where
+/// `password` is the token and optional `username` makes it a personal access token.
+pub fn run_helper(command: &str, profile: &str) -> Result<Login> {
+    let failure = |message: String| {
+        Error::new(ErrorKind::CredentialHelper {
+            command: command.to_owned(),
+            message,
+        })
+    };
+
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("GIST_PROFILE", profile)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| failure(e.to_string()))?;
+    if !output.status.success() {
+        return Err(failure(output.status.to_string()));
+    }
+    let stdout = String::from_utf8(output.stdout).map_err(|e| failure(e.to_string()))?;
+
+    parse_helper_output(&stdout).map_err(|message| failure(message.to_owned()))
+}
+
+/// Reads either the token itself, or `username=` and `password=` lines like `git credential fill`.
+fn parse_helper_output(stdout: &str) -> std::result::Result<Login, &'static str> {
+    let mut key_value = false;
+    let mut username = None;
+    let mut password = None;
+    for line in stdout.lines() {
+        match line.split_once('=') {
+            Some(("username", v)) => username = Some(v.to_owned()),
+            Some(("password", v)) => password = Some(v.to_owned()),
+            Some(_) => {}
+            None => continue,
+        }
+        key_value = true;
+    }
+
+    match (username, password) {
+        (Some(username), Some(token)) => Ok(Login::PersonalAccessToken { username, token }),
+        (None, Some(token)) => Ok(Login::OAuth(token)),
+        // The other lines are not the token, and must not be sent as it
+        _ if key_value => Err("no password in the output"),
+        _ => match stdout.trim() {
+            "" => Err("no credential in the output"),
+            token => Ok(Login::OAuth(token.to_owned())),
+        },
+    }
+}
+
+/// Stores the credentials of all profiles in a file, encrypted with ChaCha20-Poly1305 using the
+/// key derived from the passphrase by scrypt. The passphrase is read from `GIST_PASSPHRASE`, or
+/// asked on the terminal.
+pub struct EncryptedFile {
+    path: PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedContent {
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+type Credentials = BTreeMap<String, Login>;
+
+impl EncryptedFile {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        EncryptedFile {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    fn read(&self, passphrase: &str) -> Result<Credentials> {
+        let file = File::open(&self.path)?;
+        let invalid = || {
+            Error::new(ErrorKind::InvalidCredentialFile {
+                path: self.path.clone(),
+            })
+        };
+
+        let content: EncryptedContent =
+            serde_json::from_reader(BufReader::new(file)).map_err(|_| invalid())?;
+        let salt = base64::decode(&content.salt).map_err(|_| invalid())?;
+        let nonce = base64::decode(&content.nonce).map_err(|_| invalid())?;
+        let ciphertext = base64::decode(&content.ciphertext).map_err(|_| invalid())?;
+        if nonce.len() != 12 {
+            return Err(invalid());
+        }
+
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt));
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| invalid())?;
+        serde_json::from_slice(&plaintext).map_err(|_| invalid())
+    }
+
+    fn write(&self, passphrase: &str, credentials: &Credentials) -> Result<()> {
+        let mut salt = [0u8; 16];
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let plaintext = serde_json::to_vec(credentials).unwrap();
+        let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt));
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+            .unwrap();
+
+        let content = EncryptedContent {
+            salt: base64::encode(salt),
+            nonce: base64::encode(nonce),
+            ciphertext: base64::encode(&ciphertext),
+        };
+        let writer = BufWriter::new(config::create_private_file(&self.path)?);
+        match serde_json::to_writer_pretty(writer, &content) {
+            Ok(()) => Ok(()),
+            Err(error) => Err(Error::new(ErrorKind::SaveConfigFailure {
+                path: self.path.clone(),
+                error,
+            })),
+        }
+    }
+
+    fn passphrase(&self, confirm: bool) -> Result<String> {
+        if let Ok(passphrase) = std::env::var("GIST_PASSPHRASE") {
+            return Ok(passphrase);
+        }
+
+        let passphrase = rpassword::prompt_password("Passphrase: ")?;
+        if confirm && rpassword::prompt_password("Confirm passphrase: ")? != passphrase {
+            return Err(Error::new(ErrorKind::PassphraseMismatch));
+        }
+        Ok(passphrase)
+    }
+}
+
+impl CredentialStore for EncryptedFile {
+    fn load(&self, profile: &str) -> Result<Option<Login>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let mut credentials = self.read(&self.passphrase(false)?)?;
+        Ok(credentials.remove(profile))
+    }
+
+    fn save(&self, profile: &str, login: &Login) -> Result<()> {
+        let exists = self.path.exists();
+        let passphrase = self.passphrase(!exists)?;
+        let mut credentials = if exists {
+            self.read(&passphrase)?
+        } else {
+            Credentials::new()
+        };
+        credentials.insert(profile.to_owned(), login.clone());
+        self.write(&passphrase, &credentials)
+    }
+
+    fn delete(&self, profile: &str) -> Result<()> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+        let passphrase = self.passphrase(false)?;
+        let mut credentials = self.read(&passphrase)?;
+        if credentials.remove(profile).is_some() {
+            self.write(&passphrase, &credentials)?;
+        }
+        Ok(())
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Key {
+    // The recommended parameters for interactive logins
+    let params = scrypt::Params::new(15, 8, 1, 32).unwrap();
+    let mut key = Key::default();
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key).unwrap();
+    key
+}
+
+/// Stores the credential of each profile as an entry of the OS keyring, serialized as JSON.
+#[cfg(feature = "keyring")]
+pub struct Keyring;
+
+#[cfg(feature = "keyring")]
+impl Keyring {
+    fn entry(profile: &str) -> Result<keyring::Entry> {
+        keyring::Entry::new("gist", profile).map_err(|e| Error::new(ErrorKind::Keyring(e)))
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl CredentialStore for Keyring {
+    fn load(&self, profile: &str) -> Result<Option<Login>> {
+        let secret = match Keyring::entry(profile)?.get_password() {
+            Ok(secret) => secret,
+            Err(keyring::Error::NoEntry) => return Ok(None),
+            Err(e) => return Err(Error::new(ErrorKind::Keyring(e))),
+        };
+        match serde_json::from_str(&secret) {
+            Ok(login) => Ok(Some(login)),
+            Err(_) => Err(Error::new(ErrorKind::InvalidKeyringEntry {
+                profile: profile.to_owned(),
+            })),
+        }
+    }
+
+    fn save(&self, profile: &str, login: &Login) -> Result<()> {
+        let secret = serde_json::to_string(login).unwrap();
+        Keyring::entry(profile)?
+            .set_password(&secret)
+            .map_err(|e| Error::new(ErrorKind::Keyring(e)))
+    }
+
+    fn delete(&self, profile: &str) -> Result<()> {
+        match Keyring::entry(profile)?.delete_password() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(Error::new(ErrorKind::Keyring(e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_helper_token() {
+        assert_eq!(
+            parse_helper_output("token\n"),
+            Ok(Login::OAuth("token".to_owned()))
+        );
+        assert_eq!(
+            parse_helper_output("password=token\n"),
+            Ok(Login::OAuth("token".to_owned()))
+        );
+        assert_eq!(
+            parse_helper_output("protocol=https\nusername=octocat\npassword=token\n"),
+            Ok(Login::PersonalAccessToken {
+                username: "octocat".to_owned(),
+                token: "token".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_helper_without_password() {
+        assert!(parse_helper_output("").is_err());
+        assert!(parse_helper_output("username=octocat\n").is_err());
+        assert!(parse_helper_output("protocol=https\nhost=github.com\n").is_err());
+    }
+
+    #[test]
+    fn encrypted_file_round_trip() {
+        let path = std::env::temp_dir().join(format!("gist-test-{}.json", std::process::id()));
+        let file = EncryptedFile::new(&path);
+        let mut credentials = Credentials::new();
+        credentials.insert("default".to_owned(), Login::OAuth("token".to_owned()));
+
+        file.write("passphrase", &credentials).unwrap();
+        let read = file.read("passphrase");
+        let wrong = file.read("wrong");
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.unwrap(), credentials);
+        assert!(matches!(
+            wrong.unwrap_err().kind(),
+            ErrorKind::InvalidCredentialFile { .. }
+        ));
+    }
+}
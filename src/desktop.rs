@@ -0,0 +1,83 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Opens the URL in the browser specified with `BROWSER`, or the default one of the desktop.
+/// Returns `false` if no browser could be launched.
+pub fn open_browser(url: &str) -> bool {
+    // Never let the other schemes, e.g. `file:`, reach the desktop handlers
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return false;
+    }
+
+    if let Ok(browsers) = std::env::var("BROWSER") {
+        // Same as xdg-open, `BROWSER` is a colon-separated list and `%s` is replaced with the URL.
+        // The URL is given to the shell as the positional parameter, so it is never parsed.
+        for browser in browsers.split(':').filter(|b| !b.is_empty()) {
+            let command = if browser.contains("%s") {
+                browser
+                    .replace("'%s'", "%s")
+                    .replace("\"%s\"", "%s")
+                    .replace("%s", "\"$1\"")
+            } else {
+                format!("{} \"$1\"", browser)
+            };
+            if spawn(Command::new("sh").args(["-c", &command, "sh", url])) {
+                return true;
+            }
+        }
+        return false;
+    }
+
+    if cfg!(target_os = "macos") {
+        spawn(Command::new("open").arg(url))
+    } else if cfg!(windows) {
+        // Unlike `cmd /C start`, the URL is not interpreted by cmd.exe
+        spawn(Command::new("rundll32").args(["url.dll,FileProtocolHandler", url]))
+    } else {
+        spawn(Command::new("xdg-open").arg(url))
+    }
+}
+
+/// Copies the text to the clipboard with the first available command. Returns `false` if none of
+/// them succeeded.
+pub fn copy_to_clipboard(text: &str) -> bool {
+    let commands: &[&[&str]] = &[
+        &["pbcopy"],
+        &["wl-copy"],
+        &["xclip", "-selection", "clipboard"],
+        &["xsel", "--clipboard", "--input"],
+        &["clip"],
+    ];
+    commands.iter().any(|c| pipe(c, text))
+}
+
+fn spawn(command: &mut Command) -> bool {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .is_ok()
+}
+
+fn pipe(command: &[&str], input: &str) -> bool {
+    let child = Command::new(command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(_) => return false,
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        if stdin.write_all(input.as_bytes()).is_err() {
+            return false;
+        }
+    }
+    match child.wait() {
+        Ok(status) => status.success(),
+        Err(_) => false,
+    }
+}
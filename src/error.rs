@@ -10,6 +10,10 @@ impl Error {
     pub fn new(kind: ErrorKind) -> Box<Error> {
         Box::new(Error { kind })
     }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Box<Error>>;
@@ -23,7 +27,48 @@ pub enum ErrorKind {
         status: reqwest::StatusCode,
         message: String,
     },
+    RateLimited {
+        reset: Option<chrono::DateTime<chrono::Utc>>,
+    },
+    DeviceCodeExpired,
+    PartialFailure {
+        failed: usize,
+        total: usize,
+    },
+    AccessDenied,
+    LoginTimedOut,
+    Cancelled,
+    ConfirmationRequired,
+    InvalidGistReference {
+        reference: String,
+    },
+    FileNotInGist {
+        id: String,
+        filename: String,
+    },
+    BinaryFile {
+        name: String,
+    },
+    DuplicateFileName {
+        filename: String,
+        paths: (PathBuf, PathBuf),
+    },
+    FileExists {
+        path: PathBuf,
+    },
+    IsDirectory {
+        path: PathBuf,
+    },
+    InvalidFileName {
+        path: PathBuf,
+    },
     ConfigDirectoryNotDetected,
+    NotLoggedIn,
+    ClientIdNotSpecified,
+    RevokeNotSupported,
+    ProfileNotFound {
+        name: String,
+    },
     InvalidConfigFormat {
         path: PathBuf,
         error: serde_json::Error,
@@ -32,8 +77,24 @@ pub enum ErrorKind {
         path: PathBuf,
         error: serde_json::Error,
     },
+    InvalidCredentialFile {
+        path: PathBuf,
+    },
+    InvalidKeyringEntry {
+        profile: String,
+    },
+    PassphraseMismatch,
+    KeyringNotSupported,
+    CredentialHelper {
+        command: String,
+        message: String,
+    },
+    #[cfg(feature = "keyring")]
+    Keyring(keyring::Error),
+    SerializeOutput(serde_json::Error),
     HttpClient(reqwest::Error),
     Io(std::io::Error),
+    Walk(ignore::Error),
 }
 
 impl fmt::Display for Error {
@@ -51,14 +112,62 @@ impl fmt::Display for ErrorKind {
                 "GitHub API returns error with status {}: {}",
                 status, message
             ),
+            ErrorKind::RateLimited { reset: Some(reset) } =>
+                write!(f, "GitHub API rate limit exceeded. Retry after {}", crate::output::timestamp(reset)),
+            ErrorKind::RateLimited { reset: None } => write!(f, "GitHub API rate limit exceeded"),
+            ErrorKind::PartialFailure { failed, total } =>
+                write!(f, "Failed on {} of {} Gists", failed, total),
+            ErrorKind::DeviceCodeExpired =>
+                write!(f, "The code has expired. Run 'gist login' again"),
+            ErrorKind::AccessDenied => write!(f, "The authorization was denied"),
+            ErrorKind::LoginTimedOut => write!(f, "Timed out waiting for the authorization"),
+            ErrorKind::Cancelled => write!(f, "Cancelled"),
+            ErrorKind::ConfirmationRequired =>
+                write!(f, "Cannot ask for confirmation without a terminal. Use --yes to proceed"),
+            ErrorKind::InvalidGistReference { reference } =>
+                write!(f, "'{}' is not a Gist ID or URL, e.g. 'aa5a315d61ae9438b18d' or 'https://gist.github.com/<user>/<id>'", reference),
+            ErrorKind::FileNotInGist { id, filename } =>
+                write!(f, "Gist '{}' does not have file '{}'", id, filename),
+            ErrorKind::BinaryFile { name } =>
+                write!(f, "'{}' is a binary or non-UTF-8 file. Use --binary skip to skip it or --binary base64 to upload it as base64 text", name),
+            ErrorKind::DuplicateFileName { filename, paths } =>
+                write!(f, "Both '{}' and '{}' are uploaded as '{}'", paths.0.display(), paths.1.display(), filename),
+            ErrorKind::FileExists { path } =>
+                write!(f, "File '{}' already exists. Use --force to overwrite or --skip to keep it", path.display()),
+            ErrorKind::IsDirectory { path } =>
+                write!(f, "'{}' is a directory. Use -R to upload the files in it", path.display()),
+            ErrorKind::InvalidFileName { path } =>
+                write!(f, "Cannot name '{}' on Gist. Use '<path>:<name>' to specify the name", path.display()),
             ErrorKind::ConfigDirectoryNotDetected =>
                 write!(f, "Default configuration directory not detected. $HOME or $XDG_CONFIG_FIR may not set"),
+            ErrorKind::NotLoggedIn =>
+                write!(f, "Not logged in. Run 'gist login' or specify the account with -t or -u/-p"),
+            ErrorKind::ClientIdNotSpecified =>
+                write!(f, "Client ID of OAuth App is not specified. Run 'gist login <client id>'"),
+            ErrorKind::RevokeNotSupported =>
+                write!(f, "Only OAuth2 access token can be revoked. Revoke personal access token on GitHub"),
+            ErrorKind::ProfileNotFound { name } =>
+                write!(f, "Profile '{}' not found. Run 'gist --profile {} login' to create it", name, name),
             ErrorKind::InvalidConfigFormat { path, error } =>
                 write!(f,"Cannot parse configuration file '{}': ", path.display()).and_then(move |_| error.fmt(f)),
             ErrorKind::SaveConfigFailure { path, error } =>
                 write!(f,"Failed to save configuration file '{}': ", path.display()).and_then(move |_| error.fmt(f)),
+            ErrorKind::InvalidCredentialFile { path } =>
+                write!(f, "Cannot decrypt credential file '{}'. The passphrase may be wrong", path.display()),
+            ErrorKind::InvalidKeyringEntry { profile } =>
+                write!(f, "Cannot parse the credential of profile '{}' in the keyring", profile),
+            ErrorKind::PassphraseMismatch => write!(f, "Passphrases do not match"),
+            ErrorKind::KeyringNotSupported =>
+                write!(f, "Keyring is not supported. Rebuild gist with '--features keyring'"),
+            ErrorKind::CredentialHelper { command, message } =>
+                write!(f, "Credential helper '{}' failed: {}", command, message),
+            #[cfg(feature = "keyring")]
+            ErrorKind::Keyring(e) => write!(f, "Keyring error: ").and_then(move |_| e.fmt(f)),
+            ErrorKind::SerializeOutput(e) =>
+                write!(f, "Failed to serialize output: ").and_then(move |_| e.fmt(f)),
             ErrorKind::HttpClient(e) => e.fmt(f),
             ErrorKind::Io(e) => e.fmt(f),
+            ErrorKind::Walk(e) => e.fmt(f),
         }
     }
 }
@@ -75,11 +184,20 @@ impl From<std::io::Error> for Box<Error> {
     }
 }
 
+impl From<ignore::Error> for Box<Error> {
+    fn from(e: ignore::Error) -> Box<Error> {
+        Error::new(ErrorKind::Walk(e))
+    }
+}
+
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match &self.kind {
             ErrorKind::HttpClient(e) => Some(e),
             ErrorKind::Io(e) => Some(e),
+            ErrorKind::Walk(e) => Some(e),
+            #[cfg(feature = "keyring")]
+            ErrorKind::Keyring(e) => Some(e),
             _ => None,
         }
     }
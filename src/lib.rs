@@ -1,4 +1,8 @@
 pub mod api;
 pub mod app;
 pub mod config;
+pub mod credential;
+pub mod desktop;
 pub mod error;
+pub mod output;
+pub mod reference;
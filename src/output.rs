@@ -0,0 +1,257 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+
+use crate::api;
+use crate::error::{Error, ErrorKind, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Tsv,
+    Table,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "tsv" => Ok(Format::Tsv),
+            "table" => Ok(Format::Table),
+            _ => Err(format!("invalid value '{}'", s)),
+        }
+    }
+}
+
+/// A value printed as an object in `json`, or as a row in `tsv` and `table`.
+pub trait Record: Serialize {
+    fn columns() -> &'static [&'static str];
+    fn fields(&self) -> Vec<String>;
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Status {
+    pub id: String,
+    pub status: String,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct AuthStatus {
+    pub user: String,
+    pub token_type: String,
+    pub scopes: Option<Vec<String>>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FileStatus {
+    pub path: String,
+    pub status: String,
+}
+
+/// Prints the records. `json` prints them as an array, while `text` prints the fields separated
+/// by a space for the commands having no dedicated text output.
+pub fn print<T: Record>(format: Format, records: &[T]) -> Result<()> {
+    match format {
+        Format::Json => print_json(&records),
+        Format::Text => {
+            for r in records.iter() {
+                println!("{}", r.fields().join(" "));
+            }
+            Ok(())
+        }
+        Format::Tsv => {
+            for r in records.iter() {
+                let fields: Vec<_> = r.fields().iter().map(|f| escape(f)).collect();
+                println!("{}", fields.join("\t"));
+            }
+            Ok(())
+        }
+        Format::Table => {
+            let header = T::columns().iter().map(|c| c.to_uppercase()).collect();
+            let rows: Vec<Vec<String>> = std::iter::once(header)
+                .chain(
+                    records
+                        .iter()
+                        .map(|r| r.fields().iter().map(|f| escape(f)).collect()),
+                )
+                .collect();
+            print_table(&rows);
+            Ok(())
+        }
+    }
+}
+
+/// Prints the record. Unlike `print`, `json` prints it as a single object.
+pub fn print_one<T: Record>(format: Format, record: &T) -> Result<()> {
+    match format {
+        Format::Json => print_json(record),
+        _ => print(format, std::slice::from_ref(record)),
+    }
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    match serde_json::to_string_pretty(value) {
+        Ok(s) => {
+            println!("{}", s);
+            Ok(())
+        }
+        Err(e) => Err(Error::new(ErrorKind::SerializeOutput(e))),
+    }
+}
+
+fn print_table(rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows.iter() {
+        for (i, f) in row.iter().enumerate() {
+            let len = f.chars().count();
+            if i < widths.len() {
+                widths[i] = widths[i].max(len);
+            } else {
+                widths.push(len);
+            }
+        }
+    }
+
+    for row in rows.iter() {
+        let line: Vec<_> = row
+            .iter()
+            .enumerate()
+            .map(|(i, f)| format!("{:1$}", f, widths[i]))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+/// Formats the timestamp in the same way as GitHub API, e.g. `2010-04-14T02:15:15Z`.
+pub fn timestamp(t: &DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+impl Record for api::GistResponse {
+    fn columns() -> &'static [&'static str] {
+        &[
+            "id",
+            "html_url",
+            "visibility",
+            "owner",
+            "updated_at",
+            "files",
+            "description",
+        ]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.html_url.clone(),
+            if self.public { "public" } else { "secret" }.to_owned(),
+            self.owner
+                .as_ref()
+                .map(|u| u.login.clone())
+                .unwrap_or_default(),
+            timestamp(&self.updated_at),
+            self.files.keys().cloned().collect::<Vec<_>>().join(","),
+            self.description.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+impl Record for api::GistFile {
+    fn columns() -> &'static [&'static str] {
+        &["filename", "type", "language", "size", "raw_url"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.filename.clone(),
+            self.file_type.clone().unwrap_or_default(),
+            self.language.clone().unwrap_or_default(),
+            self.size.to_string(),
+            self.raw_url.clone(),
+        ]
+    }
+}
+
+impl Record for api::GistHistory {
+    fn columns() -> &'static [&'static str] {
+        &["version", "user", "committed_at", "additions", "deletions"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.version.clone(),
+            self.user
+                .as_ref()
+                .map(|u| u.login.clone())
+                .unwrap_or_default(),
+            timestamp(&self.committed_at),
+            self.change_status.additions.to_string(),
+            self.change_status.deletions.to_string(),
+        ]
+    }
+}
+
+impl Record for api::CommentResponse {
+    fn columns() -> &'static [&'static str] {
+        &["id", "user", "created_at", "body"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.user
+                .as_ref()
+                .map(|u| u.login.clone())
+                .unwrap_or_default(),
+            timestamp(&self.created_at),
+            self.body.clone(),
+        ]
+    }
+}
+
+impl Record for Status {
+    fn columns() -> &'static [&'static str] {
+        &["id", "status"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![self.id.clone(), self.status.clone()]
+    }
+}
+
+impl Record for FileStatus {
+    fn columns() -> &'static [&'static str] {
+        &["path", "status"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![self.path.clone(), self.status.clone()]
+    }
+}
+
+impl Record for AuthStatus {
+    fn columns() -> &'static [&'static str] {
+        &["user", "token_type", "scopes"]
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.user.clone(),
+            self.token_type.clone(),
+            self.scopes
+                .as_ref()
+                .map(|s| s.join(","))
+                .unwrap_or_default(),
+        ]
+    }
+}
@@ -0,0 +1,127 @@
+use reqwest::Url;
+
+use crate::error::{Error, ErrorKind, Result};
+
+/// Parses the reference to a Gist and returns its ID. The following forms are accepted:
+///
+/// - ID: `aa5a315d61ae9438b18d`
+/// - Owner and ID: `octocat/aa5a315d61ae9438b18d`
+/// - URL of the Gist: `https://gist.github.com/octocat/aa5a315d61ae9438b18d`
+/// - URL on GitHub Enterprise Server: `https://github.example.com/gist/octocat/aa5a315d61ae9438b18d`
+/// - URL of the raw file: `https://gist.githubusercontent.com/octocat/aa5a315d61ae9438b18d/raw/...`
+/// - URL of the Git repository: `https://gist.github.com/aa5a315d61ae9438b18d.git`
+pub fn parse_id(reference: &str) -> Result<String> {
+    let invalid = || {
+        Error::new(ErrorKind::InvalidGistReference {
+            reference: reference.to_owned(),
+        })
+    };
+
+    let s = reference.trim();
+    if is_id(s) {
+        return Ok(s.to_owned());
+    }
+    if let Some((owner, id)) = s.split_once('/') {
+        if is_owner(owner) && is_id(id) {
+            return Ok(id.to_owned());
+        }
+    }
+
+    let url = match Url::parse(s) {
+        Ok(url) if url.scheme() == "https" || url.scheme() == "http" => url,
+        _ => return Err(invalid()),
+    };
+    let host = url.host_str().ok_or_else(invalid)?;
+    let mut segments: Vec<_> = url
+        .path_segments()
+        .ok_or_else(invalid)?
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    // GitHub.com serves Gists on the dedicated hosts, e.g. gist.github.com, while GitHub
+    // Enterprise Server does under /gist on the same host
+    if !host.starts_with("gist.") {
+        match segments.first() {
+            Some(&"gist") => {
+                segments.remove(0);
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    let id = match segments.as_slice() {
+        [id] => id.trim_end_matches(".git"),
+        [owner, id] if is_owner(owner) => id.trim_end_matches(".git"),
+        [owner, id, "raw", ..] | [owner, id, "revisions"] if is_owner(owner) => id,
+        _ => return Err(invalid()),
+    };
+    if is_id(id) {
+        Ok(id.to_owned())
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Gist IDs are hexadecimal, or decimal for the old ones.
+fn is_id(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn is_owner(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_references() {
+        for reference in &[
+            "aa5a315d61ae9438b18d",
+            " aa5a315d61ae9438b18d\n",
+            "octocat/aa5a315d61ae9438b18d",
+            "https://gist.github.com/aa5a315d61ae9438b18d",
+            "https://gist.github.com/octocat/aa5a315d61ae9438b18d",
+            "https://gist.github.com/octocat/aa5a315d61ae9438b18d/",
+            "https://gist.github.com/octocat/aa5a315d61ae9438b18d#file-hello_world-rb",
+            "https://gist.github.com/octocat/aa5a315d61ae9438b18d/revisions",
+            "https://gist.github.com/aa5a315d61ae9438b18d.git",
+            "https://gist.githubusercontent.com/octocat/aa5a315d61ae9438b18d/raw/hello_world.rb",
+            "https://github.example.com/gist/octocat/aa5a315d61ae9438b18d",
+            "http://github.example.com/gist/aa5a315d61ae9438b18d.git",
+        ] {
+            assert_eq!(
+                parse_id(reference).ok().as_deref(),
+                Some("aa5a315d61ae9438b18d"),
+                "{}",
+                reference
+            );
+        }
+    }
+
+    #[test]
+    fn reject_invalid_references() {
+        for reference in &[
+            "",
+            "not-an-id",
+            "octocat/",
+            "/aa5a315d61ae9438b18d",
+            "octo_cat/aa5a315d61ae9438b18d",
+            "ftp://gist.github.com/aa5a315d61ae9438b18d",
+            "https://github.com/octocat/aa5a315d61ae9438b18d",
+            "https://gist.github.com/",
+            "https://gist.github.com/octocat",
+            "https://gist.github.com/octocat/aa5a315d61ae9438b18d/stars",
+        ] {
+            assert!(
+                matches!(
+                    parse_id(reference).unwrap_err().kind(),
+                    ErrorKind::InvalidGistReference { .. }
+                ),
+                "{}",
+                reference
+            );
+        }
+    }
+}